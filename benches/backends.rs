@@ -0,0 +1,75 @@
+use std::hint::black_box;
+use std::io::{Empty, Sink};
+use std::time::{Duration, Instant};
+use brain_fuck_interpreter::{Backend, Config, run_with_io};
+
+const ITERATIONS: usize = 3;
+
+/// The corpus compiled to Rust by `include_bf!`, with bounds checks unless they provably
+/// aren't needed.
+mod generated {
+    use proc_macro_bf::include_bf;
+
+    include_bf!{hello, "benches/corpus/hello.b", input = reader, output = writer, return_tape, quiet}
+    include_bf!{nested_loops, "benches/corpus/nested_loops.b", input = reader, output = writer, return_tape, quiet}
+    include_bf!{transfer, "benches/corpus/transfer.b", input = reader, output = writer, return_tape, quiet}
+    include_bf!{mandelbrot, "src/mandelbrot.b", input = reader, output = writer, return_tape, quiet}
+
+    /// Without bounds checks, as the `use_codegen` build runs mandelbrot.
+    pub mod unchecked {
+        use proc_macro_bf::include_bf;
+
+        include_bf!{hello, "benches/corpus/hello.b", input = reader, output = writer, return_tape, unsafe, padding = 16, quiet}
+        include_bf!{nested_loops, "benches/corpus/nested_loops.b", input = reader, output = writer, return_tape, unsafe, padding = 16, quiet}
+        include_bf!{transfer, "benches/corpus/transfer.b", input = reader, output = writer, return_tape, unsafe, padding = 16, quiet}
+        include_bf!{mandelbrot, "src/mandelbrot.b", input = reader, output = writer, return_tape, unsafe, padding = 16, quiet}
+    }
+}
+
+type Generated = fn(Empty, Sink) -> std::io::Result<Vec<u8>>;
+
+const CORPUS: &[(&str, &str, Generated, Generated)] = &[
+    ("hello", include_str!("corpus/hello.b"), generated::hello, generated::unchecked::hello),
+    ("nested_loops", include_str!("corpus/nested_loops.b"), generated::nested_loops, generated::unchecked::nested_loops),
+    ("transfer", include_str!("corpus/transfer.b"), generated::transfer, generated::unchecked::transfer),
+    ("mandelbrot", include_str!("../src/mandelbrot.b"), generated::mandelbrot, generated::unchecked::mandelbrot),
+];
+
+const BACKENDS: &[Backend] = &[Backend::Switch, Backend::Threaded, Backend::Jit];
+
+fn fastest(run: impl Fn()) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let instant = Instant::now();
+            run();
+            instant.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(name: &str, variant: &str, elapsed: Duration) {
+    println!("{name:>14} {variant:>10}: {:>10.3} ms", elapsed.as_secs_f64() * 1000.0);
+}
+
+/// Compares the backends and the code generated by `include_bf!` on the benchmark corpus,
+/// `cargo bench -- <name>` runs a single program.
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    for (name, code, generated, unchecked) in CORPUS {
+        if filter.as_deref().is_some_and(|filter| !name.contains(filter)) {
+            continue;
+        }
+        for &backend in BACKENDS {
+            let config = Config { backend, ..Config::default() };
+            let elapsed = fastest(|| run_with_io(code, config, std::io::empty(), std::io::sink()).unwrap());
+            report(name, &format!("{backend:?}"), elapsed);
+        }
+        report(name, "Codegen", fastest(|| {
+            black_box(generated(std::io::empty(), std::io::sink()).unwrap());
+        }));
+        report(name, "Unchecked", fastest(|| {
+            black_box(unchecked(std::io::empty(), std::io::sink()).unwrap());
+        }));
+    }
+}
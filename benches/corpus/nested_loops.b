@@ -0,0 +1,13 @@
+Three nested loops of 100 iterations each with a copy loop of another
+100 iterations inside which the optimizer does not recognize
+++++++++++[>++++++++++<-]>
+[
+  >++++++++++[>++++++++++<-]>
+  [
+    >++++++++++[>++++++++++<-]>
+    [->+>+<<]>[-]>[-]<<
+    <<-
+  ]
+  <<-
+]
+++++++++++.
@@ -0,0 +1,11 @@
+Shuffles a value back and forth over a long distance on the tape
+using the copy loops recognized by the optimizer
+++++++++++[>++++++++++<-]>[>
+  +++++++++++++++++++++++++[>++++++++++<-]>
+  [
+    [->>>>>>>>>>+<<<<<<<<<<]>>>>>>>>>>
+    [-<<<<<<<<<<+>>>>>>>>>>]<<<<<<<<<<
+    -
+  ]
+<<-]
+++++++++++.
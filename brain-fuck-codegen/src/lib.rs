@@ -0,0 +1,657 @@
+//! Translation of the optimized `Node` tree into Rust tokens, shared by the `bf!` macro
+//! and the ahead-of-time Rust emitter.
+
+use std::ops::RangeInclusive;
+use proc_macro2::{Literal, TokenStream};
+use quote::{ToTokens, quote};
+
+use brain_fuck_parser::{DEBUG_WINDOW, Evaluation, Node};
+
+/// Where `,` reads from.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Input {
+    /// `libc::getchar`.
+    Stdin,
+    /// An `input: &[u8]` parameter.
+    Slice,
+    /// An `input: impl std::io::Read` parameter, unbuffered.
+    Reader
+}
+
+/// Where `.` writes to.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Output {
+    /// Stdout, unbuffered like `print!`.
+    Stdout,
+    /// A returned `Vec<u8>`.
+    Vec,
+    /// An `output: impl std::io::Write` parameter, flushed at the end.
+    Writer
+}
+
+/// Integer type of the tape cells. Programs for cells wider than a byte are to be parsed with
+/// `try_parse_bf_wide`, which doesn't fold runs of `+` and `-` modulo 256.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cell {
+    U8,
+    U16,
+    U32,
+    U64
+}
+
+impl Cell {
+    /// Cast of a byte to the cell type, none for `u8`.
+    fn cast_from_byte(self) -> TokenStream {
+        match self {
+            Cell::U8 => quote!(),
+            _ => {
+                let ty = self.ty();
+                quote!(as #ty)
+            }
+        }
+    }
+
+    /// Cast of a cell to a byte, none for `u8`.
+    fn cast_to_byte(self) -> TokenStream {
+        match self {
+            Cell::U8 => quote!(),
+            _ => quote!(as u8)
+        }
+    }
+
+    fn ty(self) -> TokenStream {
+        match self {
+            Cell::U8 => quote!(u8),
+            Cell::U16 => quote!(u16),
+            Cell::U32 => quote!(u32),
+            Cell::U64 => quote!(u64)
+        }
+    }
+}
+
+/// Shape of the generated function.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Options {
+    pub tape_size: usize,
+    pub cell: Cell,
+    pub input: Input,
+    pub output: Output,
+    /// Whether the final tape is returned, after the output if that is returned too.
+    pub return_tape: bool,
+    /// Whether cells are accessed through a raw pointer without bounds checks. A program
+    /// leaving the tape, padding included, is undefined behavior then.
+    pub unchecked: bool,
+    /// Extra cells on both sides of the tape. In unchecked mode, padding at least as wide as
+    /// the offset of a transfer to the left lets it skip checking the current cell for zero.
+    pub padding: usize
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            tape_size: 0x100000,
+            cell: Cell::U8,
+            input: Input::Stdin,
+            output: Output::Stdout,
+            return_tape: false,
+            unchecked: false,
+            padding: 0
+        }
+    }
+}
+
+impl Options {
+    /// Whether i/o errors are possible, making the function return `std::io::Result`.
+    fn fallible(&self) -> bool {
+        self.input == Input::Reader || self.output == Output::Writer
+    }
+
+    /// Whether a program touching the cells in `extent`, relative to the starting one,
+    /// never leaves the tape.
+    pub fn contains(&self, extent: &RangeInclusive<isize>) -> bool {
+        let padding = self.padding as isize;
+        *extent.start() >= -padding && *extent.end() < self.tape_size as isize + padding
+    }
+}
+
+/// Cells the program may touch, relative to the starting one, or `None` if that depends on
+/// the tape contents, which is the case for scans and loops moving the cell pointer.
+pub fn tape_extent(program: &Node) -> Option<RangeInclusive<isize>> {
+    let (mut pos, mut min, mut max) = (0, 0, 0);
+    extent(program, &mut pos, &mut min, &mut max)?;
+    Some(min..=max)
+}
+
+fn extent(node: &Node, pos: &mut isize, min: &mut isize, max: &mut isize) -> Option<()> {
+    let mut touch = |cell: isize| {
+        *min = cell.min(*min);
+        *max = cell.max(*max);
+    };
+    match node {
+        Node::Root(nodes) => {
+            for node in nodes {
+                extent(node, pos, min, max)?;
+            }
+        }
+        Node::IncTapePos(offset) => {
+            *pos += *offset as isize;
+            touch(*pos);
+        }
+        Node::DecTapePos(offset) => {
+            *pos -= *offset as isize;
+            touch(*pos);
+        }
+        // Where a call leaves the pointer depends on the procedure defined at that time.
+        Node::IncTapePosUntilEmpty | Node::DecTapePosUntilEmpty | Node::Call => return None,
+        Node::AddToTheRightAndClear(offset) | Node::DecFromTheRightAndClear(offset) => {
+            touch(*pos + *offset as isize)
+        }
+        Node::AddToTheLeftAndClear(offset) | Node::DecFromTheLeftAndClear(offset) => {
+            touch(*pos - *offset as isize)
+        }
+        Node::Fork => touch(*pos + 1),
+        Node::Loop(nodes) => {
+            let start = *pos;
+            for node in nodes {
+                extent(node, pos, min, max)?;
+            }
+            // Every iteration touches the same cells only if the body returns to where it started.
+            if *pos != start {
+                return None;
+            }
+        }
+        Node::Inc(_) | Node::Dec(_) | Node::PutChar | Node::GetChar | Node::Clear | Node::Comment { .. }
+        | Node::Debug | Node::Procedure(_) | Node::End | Node::Store | Node::Retrieve | Node::ShiftLeft
+        | Node::ShiftRight | Node::Not | Node::Xor | Node::And | Node::Or => {}
+    }
+    Some(())
+}
+
+/// Parameters of the function, without the parentheses.
+pub fn parameters(options: &Options) -> TokenStream {
+    let input = match options.input {
+        Input::Stdin => quote!(),
+        Input::Slice => quote!(input: &[u8],),
+        Input::Reader => quote!(mut input: impl std::io::Read,)
+    };
+    let output = match options.output {
+        Output::Stdout | Output::Vec => quote!(),
+        Output::Writer => quote!(mut output: impl std::io::Write,)
+    };
+    quote!(#input #output)
+}
+
+/// Return type of the function with the leading `->`, nothing for `()`.
+pub fn return_type(options: &Options) -> TokenStream {
+    let cell = options.cell.ty();
+    let value = match (options.output == Output::Vec, options.return_tape) {
+        (false, false) if !options.fallible() => return quote!(),
+        (false, false) => quote!(()),
+        (true, false) => quote!(Vec<u8>),
+        (false, true) => quote!(Vec<#cell>),
+        (true, true) => quote!((Vec<u8>, Vec<#cell>))
+    };
+    if options.fallible() { quote!(-> std::io::Result<#value>) } else { quote!(-> #value) }
+}
+
+/// Statements running the program on a fresh tape. They expect the parameters of
+/// `parameters` to be in scope and are followed by `return_value` in the function.
+pub fn function_body(program: &Node, options: &Options) -> TokenStream {
+    let cell = options.cell.ty();
+    let padding = options.padding;
+    let tape_len = options.tape_size + 2 * padding;
+    let input = match options.input {
+        Input::Slice => quote!(let mut input = input.iter().copied();),
+        Input::Stdin | Input::Reader => quote!()
+    };
+    let output = match options.output {
+        Output::Vec => quote!(let mut output: Vec<u8> = Vec::new();),
+        Output::Stdout | Output::Writer => quote!()
+    };
+    let scope = Scope { options, procedure: false, ends: program.ends(), storage: program.uses_storage() };
+    let statements = program.to_token_stream(scope);
+    let procedures = if program.uses_procedures() { procedure_table(scope) } else { quote!() };
+    let storage = if scope.storage { quote!(let storage: &mut #cell = &mut 0;) } else { quote!() };
+    let padding = Literal::usize_unsuffixed(padding);
+    let run = if options.unchecked {
+        quote!(
+            let mut ptr = tape.as_mut_ptr().wrapping_add(#padding);
+            unsafe {
+                #statements
+            }
+        )
+    } else {
+        quote!(
+            let mut tape_pos = #padding;
+            #statements
+        )
+    };
+    let run = if scope.ends { quote!('program: { #run }) } else { run };
+    let flush = match options.output {
+        Output::Writer => quote!(output.flush()?;),
+        Output::Stdout | Output::Vec => quote!()
+    };
+    let unpad = if options.return_tape && options.padding > 0 {
+        let tape_size = options.tape_size;
+        quote!(
+            tape.truncate(#padding + #tape_size);
+            tape.drain(..#padding);
+        )
+    } else {
+        quote!()
+    };
+    quote!(
+        let mut tape: Vec<#cell> = vec![0; #tape_len];
+        #input
+        #output
+        #storage
+        #procedures
+        #run
+        #flush
+        #unpad
+    )
+}
+
+/// Statements producing the output and final tape of a program evaluated ahead of time,
+/// standing in for `function_body`. Only meaningful for 8-bit cells.
+pub fn precomputed_body(evaluation: &Evaluation, options: &Options) -> TokenStream {
+    let bytes = Literal::byte_string(&evaluation.output);
+    let output = match options.output {
+        Output::Stdout => quote!(std::io::Write::write_all(&mut std::io::stdout(), #bytes).unwrap();),
+        Output::Vec => quote!(let mut output: Vec<u8> = #bytes.to_vec();),
+        Output::Writer => quote!(
+            output.write_all(#bytes)?;
+            output.flush()?;
+        )
+    };
+    let tape = if options.return_tape {
+        let tape_size = options.tape_size;
+        let used = evaluation.tape.iter().rposition(|&cell| cell != 0).map_or(0, |last| last + 1);
+        let cells = Literal::byte_string(&evaluation.tape[..used]);
+        quote!(
+            let mut tape: Vec<u8> = vec![0; #tape_size];
+            tape[..#used].copy_from_slice(#cells);
+        )
+    } else {
+        quote!()
+    };
+    quote!(
+        #output
+        #tape
+    )
+}
+
+/// Final expression of the function, nothing for `()`.
+pub fn return_value(options: &Options) -> TokenStream {
+    let value = match (options.output == Output::Vec, options.return_tape) {
+        (false, false) if !options.fallible() => return quote!(),
+        (false, false) => quote!(()),
+        (true, false) => quote!(output),
+        (false, true) => quote!(tape),
+        (true, true) => quote!((output, tape))
+    };
+    if options.fallible() { quote!(Ok(#value)) } else { value }
+}
+
+/// The cell `offset` cells away from the current one, as a place expression.
+fn cell(options: &Options, offset: isize) -> TokenStream {
+    let distance = offset.unsigned_abs();
+    match (options.unchecked, offset) {
+        (false, 0) => quote!(tape[tape_pos]),
+        (false, 1..) => quote!(tape[tape_pos + #distance]),
+        (false, _) => quote!(tape[tape_pos - #distance]),
+        (true, 0) => quote!((*ptr)),
+        (true, 1..) => quote!((*ptr.add(#distance))),
+        (true, _) => quote!((*ptr.sub(#distance)))
+    }
+}
+
+/// Statement moving the cell pointer by `distance` cells to the right, or the left if not `forward`.
+fn shift(options: &Options, forward: bool, distance: impl ToTokens) -> TokenStream {
+    match (options.unchecked, forward) {
+        (false, true) => quote!(tape_pos += #distance;),
+        (false, false) => quote!(tape_pos -= #distance;),
+        (true, true) => quote!(ptr = ptr.add(#distance);),
+        (true, false) => quote!(ptr = ptr.sub(#distance);)
+    }
+}
+
+/// Statements adding (`wrapping_add`) or subtracting (`wrapping_sub`) the current cell to or
+/// from the one `offset` cells away, then clearing the current cell.
+fn transfer(options: &Options, offset: isize, op: TokenStream) -> TokenStream {
+    let current = cell(options, 0);
+    let target = cell(options, offset);
+    quote!(
+        #target = #target.#op(#current);
+        #current = 0;
+    )
+}
+
+/// `transfer` to the left, skipped if the current cell is empty so that a program
+/// at the beginning of the tape doesn't leave it. Padding makes the check unnecessary.
+fn transfer_left(options: &Options, offset: usize, op: TokenStream) -> TokenStream {
+    let transfer = transfer(options, -(offset as isize), op);
+    if options.unchecked && options.padding >= offset {
+        return transfer;
+    }
+    let current = cell(options, 0);
+    quote!(
+        if #current != 0 {
+            #transfer
+        }
+    )
+}
+
+/// Where the statements being generated run, besides the `Options` of the function.
+#[derive(Copy, Clone)]
+struct Scope<'a> {
+    options: &'a Options,
+    /// Whether the statements are the body of a procedure rather than the top-level ones.
+    procedure: bool,
+    /// Whether the program contains `Node::End`. Its top-level statements are then in a
+    /// `'program` block it breaks out of, and procedures return `None` once it ran.
+    ends: bool,
+    /// Whether the program uses the storage register, a `&mut` passed on to procedures.
+    storage: bool
+}
+
+trait ToTokenStream {
+    fn to_token_stream(&self, scope: Scope) -> TokenStream;
+}
+
+/// Byte read by `,` as a cell, 255 on the end of input.
+fn read_char(options: &Options) -> TokenStream {
+    let from_byte = options.cell.cast_from_byte();
+    match options.input {
+        Input::Stdin => quote!(unsafe { libc::getchar() } as u8 #from_byte),
+        Input::Slice => quote!(input.next().unwrap_or(255) #from_byte),
+        Input::Reader => quote!({
+            let mut byte = [255u8];
+            if input.read(&mut byte)? == 0 {
+                byte[0] = 255;
+            }
+            byte[0] #from_byte
+        })
+    }
+}
+
+/// Statement writing the current cell, truncated to a byte.
+fn write_char(options: &Options) -> TokenStream {
+    let current = cell(options, 0);
+    let to_byte = options.cell.cast_to_byte();
+    match options.output {
+        Output::Stdout => quote!(
+            std::io::Write::write_all(&mut std::io::stdout(), &[#current #to_byte]).unwrap();
+        ),
+        Output::Vec => quote!(output.push(#current #to_byte);),
+        Output::Writer => quote!(output.write_all(&[#current #to_byte])?;)
+    }
+}
+
+/// Name and type of the cell pointer.
+fn position(options: &Options) -> (TokenStream, TokenStream) {
+    let cell = options.cell.ty();
+    if options.unchecked { (quote!(ptr), quote!(*mut #cell)) } else { (quote!(tape_pos), quote!(usize)) }
+}
+
+/// Parameters of a procedure after the tape and the cell pointer, i/o going through
+/// trait objects so procedures are plain functions whatever the program's i/o is. Bindings
+/// are `mut` in definitions, so calls reborrow them the same way as in the top-level function.
+fn procedure_parameters(scope: Scope, definition: bool) -> TokenStream {
+    let options = scope.options;
+    let binding = if definition { quote!(mut) } else { quote!() };
+    let input = match options.input {
+        Input::Stdin => quote!(),
+        Input::Slice => quote!(input: &mut dyn Iterator<Item = u8>,),
+        Input::Reader => quote!(input: &mut dyn std::io::Read,)
+    };
+    let output = match options.output {
+        Output::Stdout => quote!(),
+        Output::Vec => quote!(#binding output: &mut Vec<u8>,),
+        Output::Writer => quote!(output: &mut dyn std::io::Write,)
+    };
+    let cell = options.cell.ty();
+    let storage = if scope.storage { quote!(storage: &mut #cell,) } else { quote!() };
+    quote!(#input #output #storage #binding procedures: &mut [Option<Procedure>; 256])
+}
+
+/// Result type of procedures: where they leave the cell pointer, `None` if the program ended.
+fn procedure_result(scope: Scope) -> TokenStream {
+    let (_, position) = position(scope.options);
+    let position = if scope.ends { quote!(Option<#position>) } else { position };
+    if scope.options.fallible() { quote!(std::io::Result<#position>) } else { position }
+}
+
+/// The dispatch table of `Node::Procedure`, with `input` and `output` turned into the trait
+/// objects procedures take.
+fn procedure_table(scope: Scope) -> TokenStream {
+    let options = scope.options;
+    let cell = options.cell.ty();
+    let (_, position) = position(options);
+    let parameters = procedure_parameters(scope, false);
+    let result = procedure_result(scope);
+    let input = match options.input {
+        Input::Stdin => quote!(),
+        Input::Slice => quote!(let input: &mut dyn Iterator<Item = u8> = &mut input;),
+        Input::Reader => quote!(let input: &mut dyn std::io::Read = &mut input;)
+    };
+    let output = match options.output {
+        Output::Stdout | Output::Vec => quote!(),
+        Output::Writer => quote!(let output: &mut dyn std::io::Write = &mut output;)
+    };
+    quote!(
+        #[derive(Clone, Copy)]
+        struct Procedure(fn(&mut Vec<#cell>, #position, #parameters) -> #result);
+        let mut procedures: [Option<Procedure>; 256] = [None; 256];
+        #input
+        #output
+    )
+}
+
+/// Block defining the procedure numbered by the current cell as a nested function.
+fn define_procedure(scope: Scope, body: &[Node]) -> TokenStream {
+    let options = scope.options;
+    let current = cell(options, 0);
+    let cell = options.cell.ty();
+    let to_byte = options.cell.cast_to_byte();
+    let (name, position) = position(options);
+    let parameters = procedure_parameters(scope, true);
+    let inner = Scope { procedure: true, ..scope };
+    let statements: TokenStream = body.iter().map(|node| node.to_token_stream(inner)).collect();
+    let result = procedure_result(scope);
+    let value = if scope.ends { quote!(Some(#name)) } else { name.clone() };
+    let value = if options.fallible() { quote!(Ok(#value)) } else { value };
+    let statements = if options.unchecked { quote!(unsafe { #statements }) } else { statements };
+    quote!({
+        fn procedure(mut tape: &mut Vec<#cell>, mut #name: #position, #parameters) -> #result {
+            #statements
+            #value
+        }
+        procedures[(#current #to_byte) as usize] = Some(Procedure(procedure));
+    })
+}
+
+/// Statements calling the procedure numbered by the current cell, panicking if there is none.
+/// Calls are native ones, nesting them as deep as the stack allows.
+fn call_procedure(scope: Scope) -> TokenStream {
+    let options = scope.options;
+    let current = cell(options, 0);
+    let to_byte = options.cell.cast_to_byte();
+    let (name, _) = position(options);
+    let input = match options.input {
+        Input::Stdin => quote!(),
+        Input::Slice | Input::Reader => quote!(&mut *input,)
+    };
+    let output = match options.output {
+        Output::Stdout => quote!(),
+        Output::Vec => quote!(&mut output,),
+        Output::Writer => quote!(&mut *output,)
+    };
+    let storage = if scope.storage { quote!(&mut *storage,) } else { quote!() };
+    let question_mark = if options.fallible() { quote!(?) } else { quote!() };
+    let call = quote!((procedure.0)(&mut tape, #name, #input #output #storage &mut procedures) #question_mark);
+    let call = if scope.ends {
+        let end = end_program(scope);
+        quote!(match #call {
+            Some(#name) => #name,
+            None => { #end }
+        })
+    } else {
+        call
+    };
+    quote!(
+        let number = (#current #to_byte) as usize;
+        let Some(procedure) = procedures[number] else {
+            panic!("called undefined procedure {}", number);
+        };
+        #name = #call;
+    )
+}
+
+/// Statement ending the program: breaking out of the top-level statements, or returning
+/// `None` from a procedure.
+fn end_program(scope: Scope) -> TokenStream {
+    match (scope.procedure, scope.options.fallible()) {
+        (false, _) => quote!(break 'program;),
+        (true, false) => quote!(return None;),
+        (true, true) => quote!(return Ok(None);)
+    }
+}
+
+/// Block printing the tape position and the cells around it to stderr, after flushing the
+/// output so the dump shows up after what precedes it.
+fn debug_dump(options: &Options) -> TokenStream {
+    let padding = options.padding;
+    let unpadded = |position: TokenStream| match padding {
+        0 => position,
+        _ => quote!(#position - #padding as isize)
+    };
+    let tape_pos = if options.unchecked {
+        unpadded(quote!(ptr.cast_const().offset_from(tape.as_ptr())))
+    } else {
+        unpadded(quote!(tape_pos as isize))
+    };
+    let end = padding + options.tape_size;
+    let window = DEBUG_WINDOW as isize;
+    let flush = match options.output {
+        Output::Stdout => quote!(std::io::Write::flush(&mut std::io::stdout()).unwrap();),
+        Output::Vec => quote!(),
+        Output::Writer => quote!(output.flush()?;)
+    };
+    quote!({
+        let tape_pos = #tape_pos;
+        let cells = &tape[#padding..#end];
+        let start = (tape_pos - #window).clamp(0, cells.len() as isize) as usize;
+        let end = (tape_pos + #window + 1).clamp(start as isize, cells.len() as isize) as usize;
+        #flush
+        eprintln!("tape_pos {}, cells {}..{}: {:?}", tape_pos, start, end, &cells[start..end]);
+    })
+}
+
+impl ToTokenStream for Node {
+    fn to_token_stream(&self, scope: Scope) -> TokenStream {
+        let options = scope.options;
+        let current = cell(options, 0);
+        match self {
+            Node::Root(nodes) => nodes.iter().map(|node| node.to_token_stream(scope)).collect(),
+            Node::Inc(inc_amount) => {
+                let inc_amount = Literal::u8_unsuffixed(*inc_amount);
+                quote!(#current = #current.wrapping_add(#inc_amount);)
+            }
+            Node::Dec(dec_amount) => {
+                let dec_amount = Literal::u8_unsuffixed(*dec_amount);
+                quote!(#current = #current.wrapping_sub(#dec_amount);)
+            }
+            Node::IncTapePos(inc_amount) => shift(options, true, inc_amount),
+            Node::DecTapePos(dec_amount) => shift(options, false, dec_amount),
+            Node::IncTapePosUntilEmpty => {
+                let step = shift(options, true, Literal::usize_unsuffixed(1));
+                quote!(while #current != 0 { #step })
+            }
+            Node::DecTapePosUntilEmpty => {
+                let step = shift(options, false, Literal::usize_unsuffixed(1));
+                quote!(while #current != 0 { #step })
+            }
+            Node::PutChar => write_char(options),
+            Node::GetChar => {
+                let char = read_char(options);
+                quote!(#current = #char;)
+            }
+            Node::Clear => quote!(#current = 0;),
+            Node::AddToTheRightAndClear(offset) => transfer(options, *offset as isize, quote!(wrapping_add)),
+            Node::DecFromTheRightAndClear(offset) => transfer(options, *offset as isize, quote!(wrapping_sub)),
+            Node::AddToTheLeftAndClear(offset) => transfer_left(options, *offset, quote!(wrapping_add)),
+            Node::DecFromTheLeftAndClear(offset) => transfer_left(options, *offset, quote!(wrapping_sub)),
+            Node::Loop(nodes) => {
+                let statements: TokenStream = nodes
+                    .iter()
+                    .map(|node| node.to_token_stream(scope))
+                    .collect();
+
+                quote!(
+                    while #current != 0 {
+                        #statements
+                    }
+                )
+            },
+            Node::Debug => debug_dump(options),
+            Node::Procedure(nodes) => define_procedure(scope, nodes),
+            Node::Call => call_procedure(scope),
+            Node::Fork => quote! { compile_error!("`Y` threads can't be compiled to Rust"); },
+            Node::End => end_program(scope),
+            Node::Store => quote!(*storage = #current;),
+            Node::Retrieve => quote!(#current = *storage;),
+            Node::ShiftLeft => quote!(#current <<= 1;),
+            Node::ShiftRight => quote!(#current >>= 1;),
+            Node::Not => quote!(#current = !#current;),
+            Node::Xor => quote!(#current ^= *storage;),
+            Node::And => quote!(#current &= *storage;),
+            Node::Or => quote!(#current |= *storage;),
+            Node::Comment { .. } => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brain_fuck_parser::{evaluate, parse_bf};
+    use super::{Options, Output, precomputed_body, tape_extent};
+
+    #[test]
+    fn extent_covers_moves_and_transfers() {
+        assert_eq!(Some(0..=0), tape_extent(&parse_bf("+.,[-]")));
+        assert_eq!(Some(-2..=3), tape_extent(&parse_bf(">>>+<<<<<+")));
+        assert_eq!(Some(-4..=1), tape_extent(&parse_bf(">[-<<<<<+>>>>>]")));
+        assert_eq!(Some(0..=2), tape_extent(&parse_bf("+[>+>+<<-]")));
+    }
+
+    #[test]
+    fn extent_is_unknown_for_scans_and_drifting_loops() {
+        assert_eq!(None, tape_extent(&parse_bf("+[>]")));
+        assert_eq!(None, tape_extent(&parse_bf("+[>+]")));
+        assert_eq!(None, tape_extent(&parse_bf("+[[>+<-]>]")));
+    }
+
+    #[test]
+    fn precomputed_body_holds_the_output_and_the_used_tape() {
+        let evaluation = evaluate(&parse_bf("+++.>++."), 8, 100).unwrap();
+        let options = Options { tape_size: 8, output: Output::Vec, return_tape: true, ..Options::default() };
+        assert_eq!(
+            "let mut output : Vec < u8 > = b\"\\x03\\x02\" . to_vec () ; \
+             let mut tape : Vec < u8 > = vec ! [0 ; 8usize] ; \
+             tape [.. 2usize] . copy_from_slice (b\"\\x03\\x02\") ;",
+            precomputed_body(&evaluation, &options).to_string()
+        );
+    }
+
+    #[test]
+    fn padding_extends_the_tape_on_both_sides() {
+        let options = Options { tape_size: 4, ..Options::default() };
+        assert!(options.contains(&(0..=3)));
+        assert!(!options.contains(&(-1..=3)));
+        assert!(!options.contains(&(0..=4)));
+        let options = Options { padding: 2, ..options };
+        assert!(options.contains(&(-2..=5)));
+        assert!(!options.contains(&(-3..=0)));
+    }
+}
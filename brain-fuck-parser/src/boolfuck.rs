@@ -0,0 +1,45 @@
+use crate::{Extensions, Node, ParseError, try_parse_bf_with};
+
+/// Parses a Boolfuck program into the same tree as BF, for single-bit cells: `+` flips the
+/// current bit and becomes `Node::Inc(1)`, `,` reads a bit and `;` writes one, becoming
+/// `Node::GetChar` and `Node::PutChar`. `-` and `.` are comments, the other commands and the
+/// extensions are those of BF.
+///
+/// The flips of a series add up like increments, so a backend running the tree only has to
+/// keep the lowest bit of every cell.
+pub fn try_parse_boolfuck(code: &str, extensions: Extensions) -> Result<Node, ParseError> {
+    // Replacing single bytes keeps the positions of errors the same in both programs.
+    let bf: String = code
+        .chars()
+        .map(|c| match c {
+            ';' => '.',
+            '.' | '-' => ' ',
+            _ => c
+        })
+        .collect();
+    try_parse_bf_with(&bf, extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extensions, Node, ParseError, ParseErrorKind};
+    use super::try_parse_boolfuck;
+
+    #[test]
+    fn commands_map_onto_bf_nodes() {
+        assert_eq!(
+            Ok(Node::Root(vec![
+                Node::GetChar,
+                Node::Loop(vec![Node::PutChar, Node::Inc(3), Node::IncTapePos(1)]),
+                Node::DecTapePos(1),
+                Node::Clear,
+                Node::PutChar
+            ])),
+            try_parse_boolfuck(",[;+-+.+>]<[+];", Extensions::default())
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningBracket, position: 2 }),
+            try_parse_boolfuck("-.[;", Extensions::default())
+        );
+    }
+}
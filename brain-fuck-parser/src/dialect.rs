@@ -0,0 +1,250 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::{Extensions, Node, ParseError, check_brackets, try_parse_bf_with};
+
+/// A language mapping one-to-one onto BF, each command being spelled as a token.
+///
+/// A space in a token matches any run of whitespace, so `Ook. Ook?` also matches across a
+/// line break. Text matching no token is a comment, as in BF.
+#[derive(Clone, PartialEq, Eq, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dialect {
+    pub inc: String,
+    pub dec: String,
+    pub right: String,
+    pub left: String,
+    pub output: String,
+    pub input: String,
+    pub open: String,
+    pub close: String,
+    /// `#` of `Extensions::debug`, if the dialect has one.
+    #[serde(default)]
+    pub debug: Option<String>
+}
+
+/// Why a mapping file couldn't be loaded.
+#[derive(Debug)]
+pub enum DialectError {
+    Io(std::io::Error),
+    Syntax(String),
+    /// A token is empty, starts or ends with whitespace, or is used for two commands.
+    Token(String)
+}
+
+impl Display for DialectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DialectError::Io(err) => write!(f, "i/o error: {err}"),
+            DialectError::Syntax(err) => write!(f, "malformed dialect: {err}"),
+            DialectError::Token(token) => write!(f, "invalid or duplicate token {token:?}")
+        }
+    }
+}
+
+impl std::error::Error for DialectError {}
+
+impl Dialect {
+    /// Ook!, whose commands are pairs of `Ook.`, `Ook?` and `Ook!`.
+    pub fn ook() -> Self {
+        Self::orangutan("Ook")
+    }
+
+    /// Blub, Ook! with `Blub` instead of `Ook`.
+    pub fn blub() -> Self {
+        Self::orangutan("Blub")
+    }
+
+    fn orangutan(word: &str) -> Self {
+        let pair = |first: char, second: char| format!("{word}{first} {word}{second}");
+        Self {
+            inc: pair('.', '.'),
+            dec: pair('!', '!'),
+            right: pair('.', '?'),
+            left: pair('?', '.'),
+            output: pair('!', '.'),
+            input: pair('.', '!'),
+            open: pair('!', '?'),
+            close: pair('?', '!'),
+            debug: None
+        }
+    }
+
+    /// The built-in dialect called `name`: `ook` or `blub`.
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "ook" => Some(Self::ook()),
+            "blub" => Some(Self::blub()),
+            _ => None
+        }
+    }
+
+    /// Parses a TOML mapping file, with a `key = "token"` line per command, the keys being
+    /// `inc`, `dec`, `right`, `left`, `output`, `input`, `open`, `close` and optionally `debug`.
+    pub fn from_toml(mapping: &str) -> Result<Self, DialectError> {
+        let dialect: Self = toml::from_str(mapping).map_err(|err| DialectError::Syntax(err.to_string()))?;
+        dialect.validated()
+    }
+
+    /// Parses a JSON mapping file, an object with the keys of `from_toml`.
+    pub fn from_json(mapping: &str) -> Result<Self, DialectError> {
+        let dialect: Self = serde_json::from_str(mapping).map_err(|err| DialectError::Syntax(err.to_string()))?;
+        dialect.validated()
+    }
+
+    /// Reads a mapping file, JSON if its extension is `.json` and TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, DialectError> {
+        let mapping = std::fs::read_to_string(path).map_err(DialectError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&mapping),
+            _ => Self::from_toml(&mapping)
+        }
+    }
+
+    fn validated(self) -> Result<Self, DialectError> {
+        let tokens = self.tokens();
+        for (i, (token, _)) in tokens.iter().enumerate() {
+            let trimmed = token.trim() == *token && !token.is_empty();
+            if !trimmed || tokens[..i].iter().any(|(other, _)| other == token) {
+                return Err(DialectError::Token(token.to_string()));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Tokens with the BF command they stand for.
+    fn tokens(&self) -> Vec<(&str, char)> {
+        let mut tokens = vec![
+            (self.inc.as_str(), '+'),
+            (self.dec.as_str(), '-'),
+            (self.right.as_str(), '>'),
+            (self.left.as_str(), '<'),
+            (self.output.as_str(), '.'),
+            (self.input.as_str(), ','),
+            (self.open.as_str(), '['),
+            (self.close.as_str(), ']')
+        ];
+        tokens.extend(self.debug.as_deref().map(|debug| (debug, '#')));
+        tokens
+    }
+
+    /// Translates the program into BF, with nothing but commands. Unbalanced brackets
+    /// are reported at the position of their token in `source`.
+    pub fn translate(&self, source: &str) -> Result<String, ParseError> {
+        let tokens = self.tokens();
+        let mut code = String::new();
+        let mut positions = Vec::new();
+        let mut position = 0;
+        while position < source.len() {
+            let longest = tokens
+                .iter()
+                .filter_map(|(token, command)| Some((matched_len(token, &source[position..])?, *command)))
+                .max_by_key(|(len, _)| *len);
+            match longest {
+                Some((len, command)) => {
+                    code.push(command);
+                    positions.push(position);
+                    position += len;
+                }
+                None => position += source[position..].chars().next().map_or(1, char::len_utf8)
+            }
+        }
+        check_brackets(&code, Extensions::default()).map_err(|err| ParseError { position: positions[err.position], ..err })?;
+        Ok(code)
+    }
+}
+
+/// Length of the token at the start of `source`, a space in the token matching any run of
+/// whitespace.
+fn matched_len(token: &str, source: &str) -> Option<usize> {
+    let mut len = 0;
+    for (i, word) in token.split(' ').enumerate() {
+        if i > 0 {
+            let whitespace = source[len..].len() - source[len..].trim_start().len();
+            if whitespace == 0 {
+                return None;
+            }
+            len += whitespace;
+        }
+        if !source[len..].starts_with(word) {
+            return None;
+        }
+        len += word.len();
+    }
+    Some(len)
+}
+
+/// `try_parse_bf_with` on a program written in a dialect, errors pointing into `source`.
+pub fn try_parse_dialect(source: &str, dialect: &Dialect, extensions: Extensions) -> Result<Node, ParseError> {
+    try_parse_bf_with(&dialect.translate(source)?, extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extensions, ParseError, ParseErrorKind, parse_bf};
+    use super::{Dialect, DialectError, try_parse_dialect};
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    /// The program in an Ook!-like dialect, breaking lines in the middle of every third pair.
+    fn spell(code: &str, word: &str) -> String {
+        let dialect = Dialect::ook();
+        let pairs = [
+            ('+', dialect.inc), ('-', dialect.dec), ('>', dialect.right), ('<', dialect.left),
+            ('.', dialect.output), (',', dialect.input), ('[', dialect.open), (']', dialect.close)
+        ];
+        let mut source = String::new();
+        for (i, command) in code.chars().enumerate() {
+            let (_, pair) = pairs.iter().find(|(c, _)| *c == command).unwrap();
+            let pair = pair.replace("Ook", word);
+            source += &if i % 3 == 2 { pair.replace(' ', "\n") } else { pair };
+            source.push(' ');
+        }
+        source
+    }
+
+    #[test]
+    fn ook_and_blub_translate_to_the_same_tree() {
+        assert_eq!(Ok(HELLO.to_string()), Dialect::ook().translate(&spell(HELLO, "Ook")));
+        let expected = Ok(parse_bf(HELLO));
+        assert_eq!(expected, try_parse_dialect(&spell(HELLO, "Ook"), &Dialect::ook(), Extensions::default()));
+        assert_eq!(expected, try_parse_dialect(&spell(HELLO, "Blub"), &Dialect::blub(), Extensions::default()));
+    }
+
+    #[test]
+    fn unbalanced_tokens_are_reported_in_the_source() {
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedClosingBracket, position: 10 }),
+            Dialect::ook().translate("Ook. Ook. Ook? Ook!")
+        );
+    }
+
+    #[test]
+    fn mapping_files_define_dialects() {
+        let toml = r#"
+            inc = "inc"
+            dec = "dec"
+            right = "right"
+            left = "left"
+            output = "out"
+            input = "in"
+            open = "while"
+            close = "end"
+            debug = "dump"
+        "#;
+        let dialect = Dialect::from_toml(toml).unwrap();
+        assert_eq!(Ok("+[->+<]>.#".to_string()), dialect.translate("inc while dec right inc left end right out dump"));
+        // The longest token wins, here `inc` over `in`.
+        assert_eq!(Ok(",+".to_string()), dialect.translate("in inc"));
+
+        let json = r#"{"inc": "a", "dec": "b", "right": "c", "left": "d", "output": "e", "input": "f", "open": "g", "close": "h"}"#;
+        assert_eq!(Ok("+-><.,[]".to_string()), Dialect::from_json(json).unwrap().translate("abcdefgh xyz"));
+
+        assert!(matches!(Dialect::from_json(r#"{"inc": "a"}"#), Err(DialectError::Syntax(_))));
+        let duplicate = json.replace(r#""b""#, r#""a""#);
+        assert!(matches!(Dialect::from_json(&duplicate), Err(DialectError::Token(token)) if token == "a"));
+        let padded = json.replace(r#""b""#, r#"" b""#);
+        assert!(matches!(Dialect::from_json(&padded), Err(DialectError::Token(_))));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::Node;
+
+/// Output and final tape of a program that ran to completion without reading input.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Evaluation {
+    pub output: Vec<u8>,
+    pub tape: Vec<u8>
+}
+
+/// Why an evaluation stopped before the end of the program.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Stop {
+    Input,
+    /// Dumps show the state at runtime, so they aren't evaluated away.
+    Debug,
+    /// Procedures are left to the backends, which define them as the program goes.
+    Procedure,
+    /// Threads are scheduled at runtime.
+    Fork,
+    /// `Node::End` ran, the program is over.
+    End,
+    OutOfFuel,
+    OutOfTape
+}
+
+/// Tape of 8-bit cells the program runs on, with the steps it may still take.
+///
+/// Unless `epoch` is 0, the first write to every cell in an epoch is journaled in `undo`,
+/// `stamps` holding the epoch of each cell's last write, so the epoch can be rolled back.
+struct Evaluator {
+    tape: Vec<u8>,
+    tape_pos: usize,
+    storage: u8,
+    output: Vec<u8>,
+    fuel: u64,
+    epoch: u32,
+    stamps: Vec<u32>,
+    undo: Vec<(usize, u8)>
+}
+
+/// Runs the program on a zeroed tape of `tape_size` 8-bit cells, taking at most `fuel` steps:
+/// one per node executed and per loop or scan iteration.
+///
+/// Returns `None` if the program reads input, runs out of fuel or leaves the tape, in which
+/// case its output depends on something other than the program itself or is unknown, and
+/// if it dumps the tape with `Node::Debug`, which is left to happen at runtime, defines
+/// or calls a procedure, or forks. A program stopped by `Node::End` ran to completion.
+pub fn evaluate(program: &Node, tape_size: usize, fuel: u64) -> Option<Evaluation> {
+    let mut evaluator = Evaluator::new(tape_size, fuel);
+    match evaluator.run(program) {
+        Ok(()) | Err(Stop::End) => Some(Evaluation { output: evaluator.output, tape: evaluator.tape }),
+        Err(_) => None
+    }
+}
+
+/// Runs the top-level nodes of the program on a zeroed tape of `tape_size` 8-bit cells until
+/// one reads input, dumps the tape, defines or calls a procedure, forks, runs out of the
+/// `fuel` of `evaluate` or leaves the tape, and replaces those that ran with straight-line
+/// code producing the same output and tape: the output bytes built and printed in the first
+/// cell, then the storage register and the cells set one by one. The program behaves the same
+/// afterwards, as long as it runs on a tape of the same size. Once a `Node::End` runs, nothing
+/// else is left of the program but the straight-line code and the `Node::End`.
+///
+/// A loop is evaluated as a whole or not at all, so the first `,` inside a top-level loop keeps
+/// the whole loop in the program.
+pub fn partially_evaluate(program: &Node, tape_size: usize, fuel: u64) -> Node {
+    let Node::Root(nodes) = program else {
+        return program.clone();
+    };
+    let mut evaluator = Evaluator { stamps: vec![0; tape_size], ..Evaluator::new(tape_size, fuel) };
+    let mut evaluated = 0;
+    for node in nodes {
+        let (tape_pos, storage, output_len) = (evaluator.tape_pos, evaluator.storage, evaluator.output.len());
+        evaluator.epoch += 1;
+        evaluator.undo.clear();
+        match evaluator.run(node) {
+            Ok(()) => evaluated += 1,
+            Err(Stop::End) => {
+                let mut residual = evaluator.straight_line();
+                residual.push(Node::End);
+                return Node::Root(residual);
+            }
+            Err(_) => {
+                for (index, value) in evaluator.undo.drain(..).rev() {
+                    evaluator.tape[index] = value;
+                }
+                evaluator.tape_pos = tape_pos;
+                evaluator.storage = storage;
+                evaluator.output.truncate(output_len);
+                break;
+            }
+        }
+    }
+    let mut residual = evaluator.straight_line();
+    residual.extend_from_slice(&nodes[evaluated..]);
+    Node::Root(residual)
+}
+
+impl Evaluator {
+    fn new(tape_size: usize, fuel: u64) -> Self {
+        Self {
+            tape: vec![0; tape_size],
+            tape_pos: 0,
+            storage: 0,
+            output: Vec::new(),
+            fuel,
+            epoch: 0,
+            stamps: Vec::new(),
+            undo: Vec::new()
+        }
+    }
+
+    /// Nodes producing the output and leaving the tape as it is now, from a zeroed tape.
+    fn straight_line(&self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut previous = 0u8;
+        for &char in &self.output {
+            if char != previous {
+                nodes.push(Node::Inc(char.wrapping_sub(previous)));
+            }
+            nodes.push(Node::PutChar);
+            previous = char;
+        }
+        if previous != 0 {
+            nodes.push(Node::Clear);
+        }
+        if self.storage != 0 {
+            nodes.extend([Node::Inc(self.storage), Node::Store, Node::Clear]);
+        }
+        let mut pos = 0;
+        for (index, &cell) in self.tape.iter().enumerate().filter(|(_, &cell)| cell != 0) {
+            if index > pos {
+                nodes.push(Node::IncTapePos(index - pos));
+            }
+            nodes.push(Node::Inc(cell));
+            pos = index;
+        }
+        match self.tape_pos {
+            tape_pos if tape_pos > pos => nodes.push(Node::IncTapePos(tape_pos - pos)),
+            tape_pos if tape_pos < pos => nodes.push(Node::DecTapePos(pos - tape_pos)),
+            _ => {}
+        }
+        nodes
+    }
+
+    fn tick(&mut self) -> Result<(), Stop> {
+        self.fuel = self.fuel.checked_sub(1).ok_or(Stop::OutOfFuel)?;
+        Ok(())
+    }
+
+    fn current(&self) -> u8 {
+        self.tape[self.tape_pos]
+    }
+
+    fn write(&mut self, index: usize, value: u8) {
+        if self.epoch != 0 && self.stamps[index] != self.epoch {
+            self.stamps[index] = self.epoch;
+            self.undo.push((index, self.tape[index]));
+        }
+        self.tape[index] = value;
+    }
+
+    /// Index of the cell `offset` cells away from the current one.
+    fn cell(&self, offset: isize) -> Result<usize, Stop> {
+        self.tape_pos
+            .checked_add_signed(offset)
+            .filter(|&index| index < self.tape.len())
+            .ok_or(Stop::OutOfTape)
+    }
+
+    /// Adds (`sign` = 1) or subtracts (-1) the current cell to or from the one `offset` cells
+    /// away, then clears the current cell.
+    fn transfer(&mut self, offset: isize, sign: i8) -> Result<(), Stop> {
+        let target = self.cell(offset)?;
+        let value = if sign > 0 { self.current() } else { self.current().wrapping_neg() };
+        self.write(target, self.tape[target].wrapping_add(value));
+        self.write(self.tape_pos, 0);
+        Ok(())
+    }
+
+    fn run(&mut self, node: &Node) -> Result<(), Stop> {
+        self.tick()?;
+        match node {
+            Node::Root(nodes) => {
+                for node in nodes {
+                    self.run(node)?;
+                }
+            }
+            Node::Inc(amount) => self.write(self.tape_pos, self.current().wrapping_add(*amount)),
+            Node::Dec(amount) => self.write(self.tape_pos, self.current().wrapping_sub(*amount)),
+            Node::IncTapePos(offset) => self.tape_pos = self.cell(*offset as isize)?,
+            Node::DecTapePos(offset) => self.tape_pos = self.cell(-(*offset as isize))?,
+            Node::IncTapePosUntilEmpty | Node::DecTapePosUntilEmpty => {
+                let step = if matches!(node, Node::IncTapePosUntilEmpty) { 1 } else { -1 };
+                while self.current() != 0 {
+                    self.tick()?;
+                    self.tape_pos = self.cell(step)?;
+                }
+            }
+            Node::PutChar => self.output.push(self.current()),
+            Node::GetChar => return Err(Stop::Input),
+            Node::Debug => return Err(Stop::Debug),
+            Node::Procedure(_) | Node::Call => return Err(Stop::Procedure),
+            Node::Fork => return Err(Stop::Fork),
+            Node::End => return Err(Stop::End),
+            Node::Store => self.storage = self.current(),
+            Node::Retrieve => self.write(self.tape_pos, self.storage),
+            Node::ShiftLeft => self.write(self.tape_pos, self.current() << 1),
+            Node::ShiftRight => self.write(self.tape_pos, self.current() >> 1),
+            Node::Not => self.write(self.tape_pos, !self.current()),
+            Node::Xor => self.write(self.tape_pos, self.current() ^ self.storage),
+            Node::And => self.write(self.tape_pos, self.current() & self.storage),
+            Node::Or => self.write(self.tape_pos, self.current() | self.storage),
+            Node::Clear => self.write(self.tape_pos, 0),
+            Node::AddToTheRightAndClear(offset) => self.transfer(*offset as isize, 1)?,
+            Node::DecFromTheRightAndClear(offset) => self.transfer(*offset as isize, -1)?,
+            // Like the other backends, an empty cell doesn't touch the one on the left.
+            Node::AddToTheLeftAndClear(offset) if self.current() != 0 => {
+                self.transfer(-(*offset as isize), 1)?
+            }
+            Node::DecFromTheLeftAndClear(offset) if self.current() != 0 => {
+                self.transfer(-(*offset as isize), -1)?
+            }
+            Node::AddToTheLeftAndClear(_) | Node::DecFromTheLeftAndClear(_) => {}
+            Node::Loop(nodes) => {
+                while self.current() != 0 {
+                    self.tick()?;
+                    for node in nodes {
+                        self.run(node)?;
+                    }
+                }
+            }
+            Node::Comment { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extensions, Node, parse_bf, try_parse_bf_with};
+    use super::{Evaluation, evaluate, partially_evaluate};
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    #[test]
+    fn evaluates_input_free_programs() {
+        let evaluation = evaluate(&parse_bf(HELLO), 16, 10_000).unwrap();
+        assert_eq!(b"Hello World!\n".to_vec(), evaluation.output);
+        assert_eq!(16, evaluation.tape.len());
+
+        let evaluation = evaluate(&parse_bf("-->+++[-<+>]>++++[-<<->>]<-[->+<]"), 4, 100).unwrap();
+        assert_eq!(Evaluation { output: Vec::new(), tape: vec![253, 0, 255, 0] }, evaluation);
+    }
+
+    #[test]
+    fn gives_up_on_input_fuel_and_tape_bounds() {
+        assert_eq!(None, evaluate(&parse_bf("+.,."), 16, 100));
+        assert_eq!(None, evaluate(&parse_bf(HELLO), 16, 100));
+        assert_eq!(None, evaluate(&parse_bf("+[>+<]"), 16, 1_000));
+        assert_eq!(None, evaluate(&parse_bf("<+"), 16, 100));
+        assert_eq!(None, evaluate(&parse_bf("+[>+]"), 16, 1_000));
+        // A `,` that never runs doesn't matter.
+        assert!(evaluate(&parse_bf("[,]+."), 16, 100).is_some());
+        let debug = try_parse_bf_with("+#.", Extensions { debug: true, ..Extensions::default() }).unwrap();
+        assert_eq!(None, evaluate(&debug, 16, 100));
+        assert_eq!(Node::Root(vec![Node::Inc(1), Node::Debug, Node::PutChar]), partially_evaluate(&debug, 16, 100));
+    }
+
+    #[test]
+    fn prefix_before_input_becomes_straight_line_code() {
+        assert_eq!(
+            Node::Root(vec![
+                Node::Inc(3), Node::PutChar, Node::Inc(254), Node::PutChar, Node::Clear,
+                Node::Inc(1), Node::IncTapePos(1), Node::Inc(5), Node::DecTapePos(1),
+                Node::GetChar, Node::PutChar
+            ]),
+            partially_evaluate(&parse_bf("+++.--.>+++++<,."), 16, 100)
+        );
+        // The loop reading input is kept as a whole, the tape is set up for it.
+        assert_eq!(
+            Node::Root(vec![Node::Inc(2), Node::Loop(vec![Node::GetChar, Node::PutChar, Node::Dec(1)])]),
+            partially_evaluate(&parse_bf("++[,.-]"), 16, 100)
+        );
+    }
+
+    #[test]
+    fn partial_evaluation_stops_where_evaluation_would() {
+        let program = parse_bf(HELLO);
+        let residual = partially_evaluate(&program, 16, 10_000);
+        assert!(!format!("{residual:?}").contains("Loop"), "{residual:?}");
+        assert_eq!(evaluate(&program, 16, 10_000), evaluate(&residual, 16, 10_000));
+
+        // Out of fuel in the first loop: only the `+`s before it are evaluated.
+        let Node::Root(nodes) = partially_evaluate(&program, 16, 100) else { unreachable!() };
+        let Node::Root(original) = &program else { unreachable!() };
+        assert_eq!(original[1..], nodes[1..]);
+        assert_eq!(original[0], nodes[0]);
+
+        // Leaving the tape is left to the backend to report.
+        assert_eq!(parse_bf("+<+"), partially_evaluate(&parse_bf("+<+"), 16, 100));
+    }
+
+    #[test]
+    fn storage_and_end_are_evaluated() {
+        let extended = |code| try_parse_bf_with(code, Extensions { extended: true, ..Extensions::default() }).unwrap();
+        let evaluation = evaluate(&extended("++++{$>+++^~}.@."), 4, 100).unwrap();
+        assert_eq!(Evaluation { output: vec![122], tape: vec![8, 122, 0, 0] }, evaluation);
+
+        // The storage register is set up for the rest of the program.
+        assert_eq!(
+            Node::Root(vec![Node::Inc(1), Node::Store, Node::Clear, Node::IncTapePos(1), Node::GetChar, Node::End]),
+            partially_evaluate(&extended("+$[-]>,@"), 16, 100)
+        );
+        // Nothing runs after `@`.
+        assert_eq!(
+            Node::Root(vec![Node::Inc(1), Node::PutChar, Node::Clear, Node::Inc(1), Node::End]),
+            partially_evaluate(&extended("+.@,."), 16, 100)
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::Node;
+
+/// Columns a loop or procedure body is indented by per level.
+const INDENT: usize = 2;
+
+/// Layout of the source written by `format`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct FormatOptions {
+    /// Lines are wrapped to this many columns, only a longer comment word overflowing them.
+    pub width: usize,
+    /// Keep comments, on lines of their own or after the commands they followed on a line.
+    pub comments: bool
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self { width: 80, comments: false }
+    }
+}
+
+/// Writes a tree back as source, with the comments of one of `try_parse_bf_source`. The body of
+/// a loop or procedure is indented between its brackets, `[` ending a line and `]` starting one,
+/// unless the body is only commands and the whole loop fits on a line, as in `[->+<]`. Commands
+/// are wrapped to `options.width` and comments reflowed with them.
+pub fn format(program: &Node, options: &FormatOptions) -> String {
+    let mut formatter = Formatter { options, out: String::new(), line: String::new(), depth: 0 };
+    formatter.node(program);
+    formatter.break_line();
+    let len = formatter.out.trim_end().len();
+    formatter.out.truncate(len);
+    if !formatter.out.is_empty() {
+        formatter.out.push('\n');
+    }
+    formatter.out
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    out: String,
+    /// The line being written, without its indentation.
+    line: String,
+    depth: usize
+}
+
+impl Formatter<'_> {
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Root(nodes) => nodes.iter().for_each(|node| self.node(node)),
+            Node::Loop(nodes) => self.block('[', nodes, ']'),
+            Node::Procedure(nodes) => self.block('(', nodes, ')'),
+            Node::Comment { text, .. } if self.options.comments => self.comment(text),
+            Node::Comment { .. } => {}
+            _ => self.word(&node.to_string(), "")
+        }
+    }
+
+    fn block(&mut self, open: char, nodes: &[Node], close: char) {
+        let flat = nodes.iter().all(|node| match node {
+            Node::Loop(_) | Node::Procedure(_) => false,
+            Node::Comment { text, .. } => !self.options.comments || text.trim().is_empty(),
+            _ => true
+        });
+        if flat {
+            let mut inline = open.to_string();
+            for node in nodes.iter().filter(|node| !matches!(node, Node::Comment { .. })) {
+                inline += &node.to_string();
+            }
+            inline.push(close);
+            if self.indentation() + inline.len() <= self.options.width {
+                return self.word(&inline, "");
+            }
+        }
+        self.word(&open.to_string(), "");
+        self.break_line();
+        self.depth += 1;
+        nodes.iter().for_each(|node| self.node(node));
+        self.break_line();
+        self.depth -= 1;
+        self.line.push(close);
+    }
+
+    /// Reflows the words of a comment. Words before its first line break follow the commands
+    /// on the current line, the others start a new line, and an empty line of the comment
+    /// separates paragraphs.
+    fn comment(&mut self, text: &str) {
+        let segments: Vec<&str> = text.split('\n').collect();
+        let mut written = false;
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                self.break_line();
+            }
+            if segment.trim().is_empty() {
+                if i > 0 && i < segments.len() - 1 && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+                    self.out.push('\n');
+                }
+                continue;
+            }
+            for word in segment.split_whitespace() {
+                self.word(word, " ");
+            }
+            written = true;
+        }
+        // The commands following the comment start a line, as they can't follow a word of it.
+        if written {
+            self.break_line();
+        }
+    }
+
+    fn indentation(&self) -> usize {
+        self.depth * INDENT
+    }
+
+    /// Appends the word to the current line, separated from what the line already holds,
+    /// on a new line if it doesn't fit.
+    fn word(&mut self, word: &str, separator: &str) {
+        if !self.line.is_empty() && self.indentation() + self.line.len() + separator.len() + word.len() > self.options.width {
+            self.break_line();
+        }
+        if !self.line.is_empty() {
+            self.line += separator;
+        }
+        self.line += word;
+    }
+
+    fn break_line(&mut self) {
+        if !self.line.is_empty() {
+            self.out += &" ".repeat(self.indentation());
+            self.out += &self.line;
+            self.out.push('\n');
+            self.line.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extensions, Node, try_parse_bf_source, try_parse_bf_with};
+    use super::{FormatOptions, format};
+
+    const COMMENTED: &str = "\
+This program prints A
+++++++++[>++++++++<-] set the second cell to 64
+>+. and print it
+
+bye";
+
+    fn source(code: &str) -> Node {
+        try_parse_bf_source(code, Extensions::default()).unwrap()
+    }
+
+    #[test]
+    fn loops_are_indented() {
+        let program = source("++[>[-]+[ \n->\n+<]<-]>.");
+        let options = FormatOptions::default();
+        assert_eq!("++[\n  >[-]+[->+<]<-\n]>.\n", format(&program, &options));
+        let narrow = FormatOptions { width: 8, ..options };
+        assert_eq!("++[\n  >[-]+\n  [->+<]\n  <-\n]>.\n", format(&program, &narrow));
+        let narrower = FormatOptions { width: 7, ..options };
+        assert_eq!("++[\n  >[-]+\n  [\n    ->+\n    <\n  ]<-\n]>.\n", format(&program, &narrower));
+        assert_eq!("", format(&source("no commands"), &options));
+    }
+
+    #[test]
+    fn commands_wrap_at_the_width() {
+        let options = FormatOptions { width: 4, ..FormatOptions::default() };
+        assert_eq!("++++\n++>>\n[\n  ++\n  -.\n]\n", format(&source("++++++>>[++-.]"), &options));
+    }
+
+    #[test]
+    fn comments_are_optionally_kept() {
+        let program = source(COMMENTED);
+        assert_eq!(
+            Node::Comment { text: " set the second cell to 64\n".to_string(), position: 43 },
+            match &program { Node::Root(nodes) => nodes[10].clone(), _ => unreachable!() }
+        );
+        assert_eq!("++++++++[>++++++++<-]>+.\n", format(&program, &FormatOptions::default()));
+        let options = FormatOptions { width: 30, comments: true };
+        let formatted = format(&program, &options);
+        assert_eq!(
+            "This program prints A\n++++++++[>++++++++<-] set the\nsecond cell to 64\n>+. and print it\n\nbye\n",
+            formatted
+        );
+        assert_eq!(formatted, format(&source(&formatted), &options));
+        assert_eq!(try_parse_bf_with(COMMENTED, Extensions::default()), try_parse_bf_with(&formatted, Extensions::default()));
+    }
+
+    #[test]
+    fn extension_commands_are_kept() {
+        let extensions = Extensions { debug: true, procedures: true, fork: true, extended: true };
+        let code = "(#Y@$!{}~^&|):";
+        let program = try_parse_bf_source(code, extensions).unwrap();
+        assert_eq!("(#Y@$!{}~^&|):\n", format(&program, &FormatOptions::default()));
+    }
+}
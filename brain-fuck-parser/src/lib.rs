@@ -1,8 +1,23 @@
 use std::collections::VecDeque;
-use combine::{parser, between, many, Parser, token, choice, none_of};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use combine::{parser, between, many, many1, position, Parser, token, choice, eof, satisfy};
+use combine::stream::PointerOffset;
+
+mod boolfuck;
+mod dialect;
+mod eval;
+mod format;
+mod minify;
+
+pub use boolfuck::try_parse_boolfuck;
+pub use dialect::{Dialect, DialectError, try_parse_dialect};
+pub use eval::{Evaluation, evaluate, partially_evaluate};
+pub use format::{FormatOptions, format};
+pub use minify::minify;
 
 macro_rules! ref_parser {
-    ($foo:ident) => { parser(|input| { $foo().parse_stream(input).into_result() }) }
+    ($foo:ident($($arg:expr),*)) => { parser(move |input| { $foo($($arg),*).parse_stream(input).into_result() }) }
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -21,10 +36,160 @@ pub enum Node {
     DecFromTheRightAndClear(usize),
     AddToTheLeftAndClear(usize),
     DecFromTheLeftAndClear(usize),
-    Comment,
+    /// A run of characters which aren't commands, with the byte offset of its first one.
+    /// Only trees of `try_parse_bf_source` have comments, optimizing drops them.
+    Comment { text: String, position: usize },
+    /// `#` with `Extensions::debug`: prints the tape position and the cells up to
+    /// `DEBUG_WINDOW` cells away from it to stderr, as
+    /// `tape_pos 9, cells 1..18: [0, 0, 72, ...]`.
+    Debug,
+    /// `(...)` with `Extensions::procedures`: defines the procedure numbered by the current
+    /// cell, replacing any previous one. The body only runs when the procedure is called.
+    Procedure(Vec<Node>),
+    /// `:` with `Extensions::procedures`: calls the procedure numbered by the current cell.
+    Call,
+    /// `Y` with `Extensions::fork`: forks the running thread. The parent's current cell is
+    /// cleared, the child starts after the `Y` one cell to the right, which is set to 1.
+    Fork,
+    /// `@` with `Extensions::extended`: ends the program, all of its threads included.
+    End,
+    /// `$` with `Extensions::extended`: copies the current cell to the storage register, a
+    /// single cell initially 0.
+    Store,
+    /// `!` with `Extensions::extended`: copies the storage register to the current cell.
+    Retrieve,
+    /// `{` with `Extensions::extended`: shifts the bits of the current cell one to the left.
+    ShiftLeft,
+    /// `}` with `Extensions::extended`: shifts the bits of the current cell one to the right.
+    ShiftRight,
+    /// `~` with `Extensions::extended`: inverts the bits of the current cell.
+    Not,
+    /// `^` with `Extensions::extended`: xors the storage register into the current cell.
+    Xor,
+    /// `&` with `Extensions::extended`: ands the storage register into the current cell.
+    And,
+    /// `|` with `Extensions::extended`: ors the storage register into the current cell.
+    Or,
     Loop(Vec<Node>)
 }
 
+/// Cells on each side of the current one dumped by `Node::Debug`.
+pub const DEBUG_WINDOW: usize = 8;
+
+/// Opt-in extensions of the language, all disabled by default.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Extensions {
+    /// Parse `#` as `Node::Debug` rather than as a comment.
+    pub debug: bool,
+    /// Parse pbrain's `(`, `)` and `:` as `Node::Procedure` and `Node::Call`.
+    pub procedures: bool,
+    /// Parse Brainfork's `Y` as `Node::Fork`.
+    pub fork: bool,
+    /// Parse Extended Brainfuck Type I's `@`, `$`, `!`, `{`, `}`, `~`, `^`, `&` and `|` as
+    /// `Node::End` and the operations on the storage register and bits.
+    pub extended: bool
+}
+
+impl FromStr for Extensions {
+    type Err = String;
+
+    /// Parses a comma separated list of extension names, e.g. `debug,pbrain,brainfork,ebf`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut extensions = Extensions::default();
+        for name in s.split(',').filter(|name| !name.is_empty()) {
+            match name {
+                "debug" => extensions.debug = true,
+                "pbrain" => extensions.procedures = true,
+                "brainfork" => extensions.fork = true,
+                "ebf" => extensions.extended = true,
+                _ => return Err(format!("unknown extension {name}, expected some of: debug, pbrain, brainfork, ebf"))
+            }
+        }
+        Ok(extensions)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ParseErrorKind {
+    UnmatchedOpeningBracket,
+    UnmatchedClosingBracket,
+    UnmatchedOpeningParenthesis,
+    UnmatchedClosingParenthesis,
+    /// A `!` separating the input from a program parsed with `Extensions::extended`, in which
+    /// `!` is `Node::Retrieve`.
+    AmbiguousInputSeparator
+}
+
+/// Syntax error of a BF program. `position` is a byte offset of the offending character.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub position: usize
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnmatchedOpeningBracket => write!(f, "unmatched '[' at {}", self.position),
+            ParseErrorKind::UnmatchedClosingBracket => write!(f, "unmatched ']' at {}", self.position),
+            ParseErrorKind::UnmatchedOpeningParenthesis => write!(f, "unmatched '(' at {}", self.position),
+            ParseErrorKind::UnmatchedClosingParenthesis => write!(f, "unmatched ')' at {}", self.position),
+            ParseErrorKind::AmbiguousInputSeparator => {
+                write!(f, "'!' at {} separates the input, so it can't also be the ebf retrieve", self.position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Writes the node as the commands it's parsed from, loops such as `Node::Clear` being
+/// written out again as `[-]`, so parsing the source of an optimized tree gives it back.
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let repeat = |c: &str, n: usize| c.repeat(n);
+        match self {
+            Node::Root(nodes) => nodes.iter().try_for_each(|node| write!(f, "{node}")),
+            Node::Inc(amount) => f.write_str(&repeat("+", *amount as usize)),
+            Node::Dec(amount) => f.write_str(&repeat("-", *amount as usize)),
+            Node::IncTapePos(shift) => f.write_str(&repeat(">", *shift)),
+            Node::DecTapePos(shift) => f.write_str(&repeat("<", *shift)),
+            Node::IncTapePosUntilEmpty => f.write_str("[>]"),
+            Node::DecTapePosUntilEmpty => f.write_str("[<]"),
+            Node::PutChar => f.write_str("."),
+            Node::GetChar => f.write_str(","),
+            Node::Clear => f.write_str("[-]"),
+            Node::AddToTheRightAndClear(shift) => write!(f, "[-{}+{}]", repeat(">", *shift), repeat("<", *shift)),
+            Node::DecFromTheRightAndClear(shift) => write!(f, "[-{}-{}]", repeat(">", *shift), repeat("<", *shift)),
+            Node::AddToTheLeftAndClear(shift) => write!(f, "[-{}+{}]", repeat("<", *shift), repeat(">", *shift)),
+            Node::DecFromTheLeftAndClear(shift) => write!(f, "[-{}-{}]", repeat("<", *shift), repeat(">", *shift)),
+            Node::Comment { text, .. } => f.write_str(text),
+            Node::Debug => f.write_str("#"),
+            Node::Procedure(nodes) => {
+                f.write_str("(")?;
+                nodes.iter().try_for_each(|node| write!(f, "{node}"))?;
+                f.write_str(")")
+            }
+            Node::Call => f.write_str(":"),
+            Node::Fork => f.write_str("Y"),
+            Node::End => f.write_str("@"),
+            Node::Store => f.write_str("$"),
+            Node::Retrieve => f.write_str("!"),
+            Node::ShiftLeft => f.write_str("{"),
+            Node::ShiftRight => f.write_str("}"),
+            Node::Not => f.write_str("~"),
+            Node::Xor => f.write_str("^"),
+            Node::And => f.write_str("&"),
+            Node::Or => f.write_str("|"),
+            Node::Loop(nodes) => {
+                f.write_str("[")?;
+                nodes.iter().try_for_each(|node| write!(f, "{node}"))?;
+                f.write_str("]")
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum SimOperation {
     Noop,
@@ -43,6 +208,25 @@ pub enum SimOperation {
     DecFromTheLeftAndClear(u32),
     JnzSaveIP { target_ip: u32 },
     JnzRestoreIP { target_ip: u32 },
+    Debug,
+    /// Makes the procedure body starting at `target_ip` the one numbered by the current cell.
+    DefineProcedure { target_ip: u32 },
+    /// Calls the procedure numbered by the current cell, pushing the return address.
+    CallProcedure,
+    /// Ends a procedure body, returning to the address pushed by `CallProcedure`.
+    Return,
+    /// Starts a thread at the next operation, see `Node::Fork`.
+    Fork,
+    /// Ends the program wherever it is, unlike `EndProgram` which only terminates the root.
+    End,
+    Store,
+    Retrieve,
+    ShiftLeft,
+    ShiftRight,
+    Not,
+    Xor,
+    And,
+    Or,
     EndProgram
 }
 
@@ -50,6 +234,7 @@ pub enum SimOperation {
 pub enum NumberedNode {
     Root( Vec<NumberedNode> ),
     Loop{ id: usize, operations: Vec<NumberedNode> },
+    Procedure{ id: usize, operations: Vec<NumberedNode> },
     Operation { id: usize, data: SimOperation }
 }
 
@@ -57,6 +242,7 @@ impl NumberedNode {
     fn get_id(&self) -> usize {
         match self {
             NumberedNode::Loop { id, .. } => *id,
+            NumberedNode::Procedure { id, .. } => *id,
             NumberedNode::Operation { id, .. } => *id,
             _ => unreachable!()
         }
@@ -67,7 +253,7 @@ impl NumberedNode {
             Node::Root(nodes) => {
                 let mut nodes: Vec<NumberedNode> = nodes
                     .iter()
-                    .map(|it| NumberedNode::from(it))
+                    .map(NumberedNode::from)
                     .collect();
                 nodes.push(NumberedNode::Operation { id: 0, data: SimOperation::EndProgram });
                 Self::Root(nodes)
@@ -111,10 +297,54 @@ impl NumberedNode {
             Node::DecFromTheLeftAndClear(offset) => {
                 Self::Operation { id: 0, data: SimOperation::DecFromTheLeftAndClear(*offset as u32) }
             }
+            Node::Debug => {
+                Self::Operation { id: 0, data: SimOperation::Debug }
+            }
+            Node::Call => {
+                Self::Operation { id: 0, data: SimOperation::CallProcedure }
+            }
+            Node::Fork => {
+                Self::Operation { id: 0, data: SimOperation::Fork }
+            }
+            Node::End => {
+                Self::Operation { id: 0, data: SimOperation::End }
+            }
+            Node::Store => {
+                Self::Operation { id: 0, data: SimOperation::Store }
+            }
+            Node::Retrieve => {
+                Self::Operation { id: 0, data: SimOperation::Retrieve }
+            }
+            Node::ShiftLeft => {
+                Self::Operation { id: 0, data: SimOperation::ShiftLeft }
+            }
+            Node::ShiftRight => {
+                Self::Operation { id: 0, data: SimOperation::ShiftRight }
+            }
+            Node::Not => {
+                Self::Operation { id: 0, data: SimOperation::Not }
+            }
+            Node::Xor => {
+                Self::Operation { id: 0, data: SimOperation::Xor }
+            }
+            Node::And => {
+                Self::Operation { id: 0, data: SimOperation::And }
+            }
+            Node::Or => {
+                Self::Operation { id: 0, data: SimOperation::Or }
+            }
+            Node::Procedure(nodes) => {
+                let mut operations = nodes
+                    .iter()
+                    .map(NumberedNode::from)
+                    .collect::<Vec<_>>();
+                operations.push(NumberedNode::Operation { id: 0, data: SimOperation::Return });
+                Self::Procedure { id: 0, operations }
+            }
             Node::Loop(nodes) => {
                 let mut operations = nodes
                     .iter()
-                    .map(|it| NumberedNode::from(it))
+                    .map(NumberedNode::from)
                     .collect::<Vec<_>>();
                 operations.push( NumberedNode::Operation {
                     id: 0,
@@ -136,7 +366,7 @@ impl NumberedNode {
         }
         while let Some(next_node) = queue.pop_front() {
             match next_node {
-                NumberedNode::Loop { id, operations } => {
+                NumberedNode::Loop { id, operations } | NumberedNode::Procedure { id, operations } => {
                     *id = id_sequence;
                     id_sequence += 1;
                     for op_node in operations.iter_mut() {
@@ -167,8 +397,7 @@ impl NumberedNode {
                     let start_id = operations.first().unwrap().get_id();
                     result[*id] = SimOperation::JnzSaveIP { target_ip: start_id as u32 };
                     let len = operations.len();
-                    let mut i = 0;
-                    for node in operations.iter_mut() {
+                    for (i, node) in operations.iter_mut().enumerate() {
                         if i == len-1 {
                             match node {
                                 NumberedNode::Operation { data, ..} => {
@@ -178,9 +407,13 @@ impl NumberedNode {
                             }
                         }
                         queue.push_back(node);
-                        i += 1;
                     }
                 }
+                NumberedNode::Procedure { operations, id } => {
+                    let start_id = operations.first().unwrap().get_id();
+                    result[*id] = SimOperation::DefineProcedure { target_ip: start_id as u32 };
+                    queue.extend(operations.iter_mut());
+                }
                 NumberedNode::Operation { data, id } => {
                     result[*id] = *data;
                 }
@@ -191,8 +424,8 @@ impl NumberedNode {
     }
 }
 
-fn parse_root<'a>() -> impl Parser<&'a str, Output = Node> {
-    many(parse_entry())
+fn parse_root(extensions: Extensions, source: &str) -> impl Parser<&str, Output = Node> {
+    many(parse_entry(extensions, source))
         .map(|nodes: Vec<Node>| Node::Root(nodes))
 }
 fn parse_inc<'a>() -> impl Parser<&'a str, Output = Node> {
@@ -220,12 +453,51 @@ fn parse_get_char<'a>() -> impl Parser<&'a str, Output = Node> {
         .map(|_| Node::GetChar)
 }
 
-fn parse_garbage<'a>() -> impl Parser<&'a str, Output = Node> {
-    none_of("+-><.,[]".chars())
-        .map(|_| Node::Comment)
+fn parse_debug<'a>(extensions: Extensions) -> impl Parser<&'a str, Output = Node> {
+    satisfy(move |c| c == '#' && extensions.debug)
+        .map(|_| Node::Debug)
+}
+
+fn parse_call<'a>(extensions: Extensions) -> impl Parser<&'a str, Output = Node> {
+    satisfy(move |c| c == ':' && extensions.procedures)
+        .map(|_| Node::Call)
 }
 
-fn parse_entry<'a>() -> impl Parser<&'a str, Output = Node> {
+fn parse_fork<'a>(extensions: Extensions) -> impl Parser<&'a str, Output = Node> {
+    satisfy(move |c| c == 'Y' && extensions.fork)
+        .map(|_| Node::Fork)
+}
+
+fn parse_extended<'a>(extensions: Extensions) -> impl Parser<&'a str, Output = Node> {
+    satisfy(move |c| "@$!{}~^&|".contains(c) && extensions.extended)
+        .map(|c| match c {
+            '@' => Node::End,
+            '$' => Node::Store,
+            '!' => Node::Retrieve,
+            '{' => Node::ShiftLeft,
+            '}' => Node::ShiftRight,
+            '~' => Node::Not,
+            '^' => Node::Xor,
+            '&' => Node::And,
+            _ => Node::Or
+        })
+}
+
+/// Whether `c` is a command of BF with `extensions`.
+fn is_command(c: char, extensions: Extensions) -> bool {
+    "+-><.,[]".contains(c)
+        || (c == '#' && extensions.debug)
+        || ("():".contains(c) && extensions.procedures)
+        || (c == 'Y' && extensions.fork)
+        || ("@$!{}~^&|".contains(c) && extensions.extended)
+}
+
+fn parse_comment(extensions: Extensions, source: &str) -> impl Parser<&str, Output = Node> {
+    (position(), many1(satisfy(move |c| !is_command(c, extensions))))
+        .map(move |(start, text): (PointerOffset<str>, String)| Node::Comment { text, position: start.translate_position(source) })
+}
+
+fn parse_entry(extensions: Extensions, source: &str) -> impl Parser<&str, Output = Node> {
     choice!(
         parse_inc(),
         parse_dec(),
@@ -233,86 +505,155 @@ fn parse_entry<'a>() -> impl Parser<&'a str, Output = Node> {
         parse_dec_tape_pos(),
         parse_get_char(),
         parse_put_char(),
-        parse_garbage(),
-        ref_parser!(parse_loop)
+        parse_debug(extensions),
+        parse_call(extensions),
+        parse_fork(extensions),
+        parse_extended(extensions),
+        parse_comment(extensions, source),
+        ref_parser!(parse_loop(extensions, source)),
+        ref_parser!(parse_procedure(extensions, source))
     )
 }
 
-fn parse_loop<'a>() -> impl Parser<&'a str, Output = Node> {
+fn parse_loop(extensions: Extensions, source: &str) -> impl Parser<&str, Output = Node> {
     between(
         token('['),
         token(']'),
-        many(parse_entry())
+        many(parse_entry(extensions, source))
     ).map(|nodes: Vec<Node>| Node::Loop(nodes))
 }
 
-pub fn parse_bf(bf_string: &str) -> Node {
-    parse_root()
+fn parse_procedure(extensions: Extensions, source: &str) -> impl Parser<&str, Output = Node> {
+    between(
+        satisfy(move |c| c == '(' && extensions.procedures),
+        token(')'),
+        many(parse_entry(extensions, source))
+    ).map(|nodes: Vec<Node>| Node::Procedure(nodes))
+}
+
+/// Checks that brackets, and parentheses with `Extensions::procedures`, are balanced and
+/// properly nested. A closing one not matching the innermost opening one is reported.
+fn check_brackets(bf_string: &str, extensions: Extensions) -> Result<(), ParseError> {
+    let mut open_brackets = Vec::new();
+    for (position, c) in bf_string.char_indices() {
+        let kind = match c {
+            '[' | '(' if c == '[' || extensions.procedures => {
+                open_brackets.push((c, position));
+                continue;
+            }
+            ']' => ParseErrorKind::UnmatchedClosingBracket,
+            ')' if extensions.procedures => ParseErrorKind::UnmatchedClosingParenthesis,
+            _ => continue
+        };
+        let opening = if c == ']' { '[' } else { '(' };
+        if open_brackets.pop().map(|(open, _)| open) != Some(opening) {
+            return Err(ParseError { kind, position });
+        }
+    }
+    match open_brackets.pop() {
+        Some(('[', position)) => Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningBracket, position }),
+        Some((_, position)) => Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningParenthesis, position }),
+        None => Ok(())
+    }
+}
+
+pub fn try_parse_bf(bf_string: &str) -> Result<Node, ParseError> {
+    try_parse_bf_with(bf_string, Extensions::default())
+}
+
+/// `try_parse_bf` with the given extensions enabled.
+pub fn try_parse_bf_with(bf_string: &str, extensions: Extensions) -> Result<Node, ParseError> {
+    let root = try_parse_bf_source(bf_string, extensions)?;
+    Ok(root.optimize_series(extensions.fork, false).optimize_loops())
+}
+
+/// `try_parse_bf_with` for cells wider than a byte: rather than folding runs of `+` and `-`
+/// modulo 256, runs of more than 255 are split into several `Node::Inc` or `Node::Dec`.
+pub fn try_parse_bf_wide(bf_string: &str, extensions: Extensions) -> Result<Node, ParseError> {
+    let root = try_parse_bf_source(bf_string, extensions)?;
+    Ok(root.optimize_series(extensions.fork, true).optimize_loops())
+}
+
+/// The tree of the program as written, for tools working on the source such as `format`:
+/// every command is a node of its own, e.g. `Node::Inc(1)`, and comments are kept.
+pub fn try_parse_bf_source(bf_string: &str, extensions: Extensions) -> Result<Node, ParseError> {
+    check_brackets(bf_string, extensions)?;
+    let root = parse_root(extensions, bf_string)
+        .skip(eof())
         .parse(bf_string)
-        .unwrap().0
-        .optimize_series()
-        .optimize_loops()
+        .expect("brackets are balanced, so the grammar accepts any input").0;
+    Ok(root)
+}
+
+pub fn parse_bf(bf_string: &str) -> Node {
+    try_parse_bf(bf_string).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Splits the source at its first `!`, after which online judges and many test files put the
+/// input of the program. Returns the program and the bytes following the `!`, none without one.
+pub fn split_inline_input(source: &str) -> (&str, &[u8]) {
+    match source.split_once('!') {
+        Some((code, input)) => (code, input.as_bytes()),
+        None => (source, &[])
+    }
+}
+
+/// `try_parse_bf_with` on the program before the first `!`, also returning the input embedded
+/// after it, see `split_inline_input`. With `Extensions::extended`, whose `Node::Retrieve` is
+/// written `!` too, a source with a `!` is rejected.
+pub fn try_parse_bf_with_input(source: &str, extensions: Extensions) -> Result<(Node, Vec<u8>), ParseError> {
+    let (code, input) = split_inline_input(source);
+    if extensions.extended && code.len() < source.len() {
+        return Err(ParseError { kind: ParseErrorKind::AmbiguousInputSeparator, position: code.len() });
+    }
+    Ok((try_parse_bf_with(code, extensions)?, input.to_vec()))
 }
 
 impl Node {
-    fn optimize_series(&self) -> Self {
+    /// `shared_tape` tells whether threads may share the tape, so that another one can write
+    /// the current cell between two nodes, `wide_cells` whether cells hold more than a byte.
+    fn optimize_series(&self, shared_tape: bool, wide_cells: bool) -> Self {
         match self {
-            Node::Root(nodes) => {
-                let mut new_nodes = Vec::with_capacity(nodes.len());
-                for node in nodes.iter() {
-                    match (node, new_nodes.last_mut()) {
-                        // the loop after loop never runs. Eliminating:
-                        (Node::Loop(_), Some(Node::Loop(_))) => {},
-                        // eliminate anything suited as a commentary chars
-                        (Node::Comment, _) => {},
-                        // eliminate empty loops
-                        (Node::Loop(loop_nodes), _) if loop_nodes.is_empty() => {},
-                        (Node::Loop(loop_nodes), _) if !loop_nodes.is_empty() => {
-                            if let Node::Loop(optimized_nodes) = node.optimize_series() {
-                                if !optimized_nodes.is_empty() {
-                                    new_nodes.push(Node::Loop(optimized_nodes));
-                                }
-                            }
-                        },
-                        // join sequential incs, decs, as well as tape position shifts
-                        (Node::Inc(amount), Some(Node::Inc(a))) => *a += amount,
-                        (Node::Dec(amount), Some(Node::Dec(a))) => *a += amount,
-                        (Node::IncTapePos(amount), Some(Node::IncTapePos(a))) => *a += amount,
-                        (Node::DecTapePos(amount), Some(Node::DecTapePos(a))) => *a += amount,
-                        _  => new_nodes.push(node.clone()),
+            Node::Root(nodes) => Node::Root(Self::optimize_sequence(nodes, shared_tape, wide_cells)),
+            Node::Loop(nodes) => Node::Loop(Self::optimize_sequence(nodes, shared_tape, wide_cells)),
+            Node::Procedure(nodes) => Node::Procedure(Self::optimize_sequence(nodes, shared_tape, wide_cells)),
+            _ => self.clone()
+        }
+    }
+
+    fn optimize_sequence(nodes: &[Node], shared_tape: bool, wide_cells: bool) -> Vec<Node> {
+        let mut new_nodes = Vec::with_capacity(nodes.len());
+        for node in nodes.iter() {
+            match (node, new_nodes.last_mut()) {
+                // the loop after loop never runs, unless another thread sets the cell. Eliminating:
+                (Node::Loop(_), Some(Node::Loop(_))) if !shared_tape => {},
+                // eliminate anything suited as a commentary chars
+                (Node::Comment { .. }, _) => {},
+                // eliminate empty loops
+                (Node::Loop(loop_nodes), _) if loop_nodes.is_empty() => {},
+                (Node::Loop(loop_nodes), _) if !loop_nodes.is_empty() => {
+                    if let Node::Loop(optimized_nodes) = node.optimize_series(shared_tape, wide_cells) {
+                        if !optimized_nodes.is_empty() {
+                            new_nodes.push(Node::Loop(optimized_nodes));
+                        }
                     }
+                },
+                // an empty procedure still replaces the previous one
+                (Node::Procedure(_), _) => new_nodes.push(node.optimize_series(shared_tape, wide_cells)),
+                // join sequential incs, decs, as well as tape position shifts, wrapping only
+                // if cells do
+                (Node::Inc(amount), Some(Node::Inc(a))) if !wide_cells || a.checked_add(*amount).is_some() => {
+                    *a = a.wrapping_add(*amount)
                 }
-                Node::Root(new_nodes)
-            },
-            Node::Loop(nodes) => {
-                let mut new_nodes = Vec::with_capacity(nodes.len());
-                for node in nodes.iter() {
-                    match (node, new_nodes.last_mut()) {
-                        // the loop after loop never runs. Eliminating:
-                        (Node::Loop(_), Some(Node::Loop(_))) => {},
-                        // eliminate anything suited as a commentary chars
-                        (Node::Comment, _) => {},
-                        // eliminate empty loops
-                        (Node::Loop(loop_nodes), _) if loop_nodes.is_empty() => {},
-                        (Node::Loop(loop_nodes), _) if !loop_nodes.is_empty() => {
-                            if let Node::Loop(optimized_nodes) = node.optimize_series() {
-                                if !optimized_nodes.is_empty() {
-                                    new_nodes.push(Node::Loop(optimized_nodes));
-                                }
-                            }
-                        },
-                        // join sequential incs, decs, as well as tape position shifts
-                        (Node::Inc(amount), Some(Node::Inc(a))) => *a += amount,
-                        (Node::Dec(amount), Some(Node::Dec(a))) => *a += amount,
-                        (Node::IncTapePos(amount), Some(Node::IncTapePos(a))) => *a += amount,
-                        (Node::DecTapePos(amount), Some(Node::DecTapePos(a))) => *a += amount,
-                        _  => new_nodes.push(node.clone()),
-                    }
+                (Node::Dec(amount), Some(Node::Dec(a))) if !wide_cells || a.checked_add(*amount).is_some() => {
+                    *a = a.wrapping_add(*amount)
                 }
-                Node::Loop(new_nodes)
-            },
-            _ => self.clone()
+                (Node::IncTapePos(amount), Some(Node::IncTapePos(a))) => *a += amount,
+                (Node::DecTapePos(amount), Some(Node::DecTapePos(a))) => *a += amount,
+                _  => new_nodes.push(node.clone()),
+            }
         }
+        new_nodes
     }
 
     fn optimize_loops(&self) -> Self {
@@ -320,21 +661,24 @@ impl Node {
             Node::Root(nodes) => {
                 Node::Root(nodes.iter().map(|it| it.optimize_loops()).collect())
             }
+            Node::Procedure(nodes) => {
+                Node::Procedure(nodes.iter().map(|it| it.optimize_loops()).collect())
+            }
             Node::Loop(nodes) => {
-                match &nodes[..] {
-                    &[Node::Dec(1)] => Node::Clear,
-                    &[Node::Inc(1)] => Node::Clear, // Eventually it will overflow to zero
-                    &[Node::IncTapePos(1)] => Node::IncTapePosUntilEmpty,
-                    &[Node::DecTapePos(1)] => Node::DecTapePosUntilEmpty,
+                match nodes[..] {
+                    [Node::Dec(1)] => Node::Clear,
+                    [Node::Inc(1)] => Node::Clear, // Eventually it will overflow to zero
+                    [Node::IncTapePos(1)] => Node::IncTapePosUntilEmpty,
+                    [Node::DecTapePos(1)] => Node::DecTapePosUntilEmpty,
 
-                    &[Node::IncTapePos(shr),
+                    [Node::IncTapePos(shr),
                     Node::Inc(1),
                     Node::DecTapePos(shl),
                     Node::Dec(1)
                     ] if shr == shl => {
                         Node::AddToTheRightAndClear(shr)
                     },
-                    &[Node::Dec(1),
+                    [Node::Dec(1),
                     Node::IncTapePos(shr),
                     Node::Inc(1),
                     Node::DecTapePos(shl)
@@ -342,14 +686,14 @@ impl Node {
                         Node::AddToTheRightAndClear(shr)
                     },
 
-                    &[Node::IncTapePos(shr),
+                    [Node::IncTapePos(shr),
                       Node::Dec(1),
                       Node::DecTapePos(shl),
                       Node::Dec(1)
                     ] if shr == shl => {
                         Node::DecFromTheRightAndClear(shr)
                     },
-                    &[Node::Dec(1),
+                    [Node::Dec(1),
                     Node::IncTapePos(shr),
                     Node::Dec(1),
                     Node::DecTapePos(shl)
@@ -357,14 +701,14 @@ impl Node {
                         Node::DecFromTheRightAndClear(shr)
                     },
 
-                    &[Node::DecTapePos(shl),
+                    [Node::DecTapePos(shl),
                     Node::Inc(1),
                     Node::IncTapePos(shr),
                     Node::Dec(1)
                     ] if shr == shl => {
                         Node::AddToTheLeftAndClear(shl)
                     },
-                    &[Node::Dec(1),
+                    [Node::Dec(1),
                     Node::DecTapePos(shl),
                     Node::Inc(1),
                     Node::IncTapePos(shr)
@@ -372,14 +716,14 @@ impl Node {
                         Node::AddToTheLeftAndClear(shl)
                     },
 
-                    &[Node::DecTapePos(shl),
+                    [Node::DecTapePos(shl),
                     Node::Dec(1),
                     Node::IncTapePos(shr),
                     Node::Dec(1)
                     ] if shr == shl => {
                         Node::DecFromTheLeftAndClear(shl)
                     },
-                    &[Node::Dec(1),
+                    [Node::Dec(1),
                     Node::DecTapePos(shl),
                     Node::Dec(1),
                     Node::IncTapePos(shr)
@@ -394,6 +738,45 @@ impl Node {
         }
     }
 
+    /// Whether the program contains a `Node::Procedure` or a `Node::Call`, so backends only set
+    /// up a table of procedures when it's needed.
+    pub fn uses_procedures(&self) -> bool {
+        match self {
+            Node::Procedure(_) | Node::Call => true,
+            Node::Root(nodes) | Node::Loop(nodes) => nodes.iter().any(Node::uses_procedures),
+            _ => false
+        }
+    }
+
+    /// Whether the program contains a `Node::Fork`, which only the interpreter can run.
+    pub fn forks(&self) -> bool {
+        match self {
+            Node::Fork => true,
+            Node::Root(nodes) | Node::Loop(nodes) | Node::Procedure(nodes) => nodes.iter().any(Node::forks),
+            _ => false
+        }
+    }
+
+    /// Whether the program contains a `Node::End`, which compiled procedures have to pass on
+    /// to their callers.
+    pub fn ends(&self) -> bool {
+        match self {
+            Node::End => true,
+            Node::Root(nodes) | Node::Loop(nodes) | Node::Procedure(nodes) => nodes.iter().any(Node::ends),
+            _ => false
+        }
+    }
+
+    /// Whether the program contains an operation on the storage register, so backends only
+    /// set one up when it's needed.
+    pub fn uses_storage(&self) -> bool {
+        match self {
+            Node::Store | Node::Retrieve | Node::Xor | Node::And | Node::Or => true,
+            Node::Root(nodes) | Node::Loop(nodes) | Node::Procedure(nodes) => nodes.iter().any(Node::uses_storage),
+            _ => false
+        }
+    }
+
     pub fn compile_bytecode(&self) -> Vec<SimOperation> {
         let mut new_tree = NumberedNode::from(self);
         let capacity = NumberedNode::numerize(&mut new_tree);
@@ -403,7 +786,10 @@ impl Node {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Node, NumberedNode, parse_bf, SimOperation};
+    use crate::{
+        Extensions, Node, NumberedNode, parse_bf, SimOperation, try_parse_bf, try_parse_bf_wide, try_parse_bf_with,
+        try_parse_bf_with_input, ParseError, ParseErrorKind
+    };
 
     #[test]
     fn numerization_test() {
@@ -665,6 +1051,151 @@ mod tests {
         assert_eq!(Node::Root(vec![]), bf);
     }
 
+    #[test]
+    fn ensure_debug_dumps_are_opt_in() {
+        assert_eq!(Node::Root(vec![Node::Inc(2)]), parse_bf("+#+"));
+
+        let extensions: Extensions = "debug".parse().unwrap();
+        assert_eq!(Extensions { debug: true, ..Extensions::default() }, extensions);
+        assert!("debug,ook".parse::<Extensions>().is_err());
+        assert_eq!(
+            Ok(Node::Root(vec![Node::Inc(1), Node::Debug, Node::Inc(1), Node::Loop(vec![Node::Dec(1), Node::Debug])])),
+            try_parse_bf_with("+#+[-#]", extensions)
+        );
+        assert_eq!(
+            vec![SimOperation::Inc(1), SimOperation::Debug, SimOperation::EndProgram],
+            try_parse_bf_with("+#", extensions).unwrap().compile_bytecode()
+        );
+    }
+
+    #[test]
+    fn ensure_procedures_are_opt_in() {
+        assert_eq!(Node::Root(vec![Node::Inc(1), Node::Dec(1)]), parse_bf("+(-:)"));
+
+        let extensions: Extensions = "pbrain".parse().unwrap();
+        assert!(extensions.procedures);
+        assert_eq!(
+            Ok(Node::Root(vec![
+                Node::Inc(1),
+                Node::Procedure(vec![Node::Dec(1), Node::IncTapePosUntilEmpty, Node::Call]),
+                Node::Procedure(vec![]),
+                Node::IncTapePos(1),
+                Node::Call
+            ])),
+            try_parse_bf_with("+(-[>]:)()>:", extensions)
+        );
+        assert_eq!(
+            vec![
+                SimOperation::Inc(1),
+                SimOperation::DefineProcedure { target_ip: 5 },
+                SimOperation::IncTapePos(1),
+                SimOperation::CallProcedure,
+                SimOperation::EndProgram,
+                SimOperation::Dec(1),
+                SimOperation::CallProcedure,
+                SimOperation::Return
+            ],
+            try_parse_bf_with("+(-:)>:", extensions).unwrap().compile_bytecode()
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedClosingParenthesis, position: 2 }),
+            try_parse_bf_with("([)]", extensions)
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningParenthesis, position: 1 }),
+            try_parse_bf_with("+(", extensions)
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedClosingBracket, position: 2 }),
+            try_parse_bf_with("(+])", extensions)
+        );
+    }
+
+    #[test]
+    fn ensure_forks_are_opt_in() {
+        assert_eq!(Node::Root(vec![Node::Inc(2)]), parse_bf("+Y+"));
+        assert!(!parse_bf("+Y+").forks());
+
+        let extensions: Extensions = "pbrain,brainfork".parse().unwrap();
+        assert_eq!(Extensions { procedures: true, fork: true, ..Extensions::default() }, extensions);
+        let program = try_parse_bf_with("Y[-Y]>(Y)", extensions).unwrap();
+        assert!(program.forks());
+        assert_eq!(
+            vec![
+                SimOperation::Fork,
+                SimOperation::JnzSaveIP { target_ip: 5 },
+                SimOperation::IncTapePos(1),
+                SimOperation::DefineProcedure { target_ip: 8 },
+                SimOperation::EndProgram,
+                SimOperation::Dec(1),
+                SimOperation::Fork,
+                SimOperation::JnzRestoreIP { target_ip: 5 },
+                SimOperation::Fork,
+                SimOperation::Return
+            ],
+            program.compile_bytecode()
+        );
+
+        // a loop after a loop may run once another thread sets the cell
+        let program = try_parse_bf_with("Y[-][.]", extensions).unwrap();
+        assert_eq!(Node::Root(vec![Node::Fork, Node::Clear, Node::Loop(vec![Node::PutChar])]), program);
+    }
+
+    #[test]
+    fn ensure_extended_operations_are_opt_in() {
+        assert_eq!(Node::Root(vec![Node::Inc(2)]), parse_bf("+@$!{}~^&|+"));
+
+        let extensions: Extensions = "ebf".parse().unwrap();
+        assert_eq!(Extensions { extended: true, ..Extensions::default() }, extensions);
+        let program = try_parse_bf_with("+$>!{}~^&|[@]", extensions).unwrap();
+        assert_eq!(
+            Node::Root(vec![
+                Node::Inc(1), Node::Store, Node::IncTapePos(1), Node::Retrieve, Node::ShiftLeft, Node::ShiftRight,
+                Node::Not, Node::Xor, Node::And, Node::Or, Node::Loop(vec![Node::End])
+            ]),
+            program
+        );
+        assert!(program.ends() && program.uses_storage());
+        let bits = try_parse_bf_with("{}~", extensions).unwrap();
+        assert!(!bits.ends() && !bits.uses_storage());
+    }
+
+    #[test]
+    fn inline_input_follows_the_first_exclamation_mark() {
+        assert_eq!(
+            Ok((Node::Root(vec![Node::GetChar, Node::PutChar]), b"ab!\n".to_vec())),
+            try_parse_bf_with_input(",.!ab!\n", Extensions::default())
+        );
+        assert_eq!(Ok((parse_bf(",."), Vec::new())), try_parse_bf_with_input(",.", Extensions::default()));
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningBracket, position: 1 }),
+            try_parse_bf_with_input("+[!]", Extensions::default())
+        );
+        let extensions = Extensions { extended: true, ..Extensions::default() };
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::AmbiguousInputSeparator, position: 3 }),
+            try_parse_bf_with_input("+$>!.", extensions)
+        );
+        assert_eq!(Ok((parse_bf("+."), Vec::new())), try_parse_bf_with_input("+.", extensions));
+    }
+
+    #[test]
+    fn ensure_unbalanced_brackets_are_reported() {
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningBracket, position: 3 }),
+            try_parse_bf("[-][+")
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedClosingBracket, position: 4 }),
+            try_parse_bf("+[-]]")
+        );
+        assert_eq!(
+            Err(ParseError { kind: ParseErrorKind::UnmatchedOpeningBracket, position: 0 }),
+            try_parse_bf("[[]")
+        );
+        assert_eq!(Ok(Node::Root(vec![Node::Clear])), try_parse_bf("[-]"));
+    }
+
     #[test]
     fn ensure_node_add_to_right_and_clear_converges() {
         let bf = parse_bf("[->+<]");
@@ -698,98 +1229,109 @@ mod tests {
         ]), bf);
     }
 
+    #[test]
+    fn ensure_long_series_wrap_around() {
+        let bf = parse_bf(&format!("{}>{}", "+".repeat(300), "-".repeat(256)));
+        assert_eq!(Node::Root(vec![Node::Inc(44), Node::IncTapePos(1), Node::Dec(0)]), bf);
+        let bf = try_parse_bf_wide(&format!("{}>{}", "+".repeat(300), "-".repeat(256)), Extensions::default());
+        assert_eq!(
+            Ok(Node::Root(vec![Node::Inc(255), Node::Inc(45), Node::IncTapePos(1), Node::Dec(255), Node::Dec(1)])),
+            bf
+        );
+    }
+
     #[test]
     fn ensure_node_series_converges() {
         let bf = Node::PutChar;
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::PutChar, bf);
 
         let bf = Node::GetChar;
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::GetChar, bf);
 
         let bf = Node::Dec(1);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Dec(1), bf);
 
         let bf = Node::Inc(1);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Inc(1), bf);
 
         let bf = Node::IncTapePos(1);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::IncTapePos(1), bf);
 
         let bf = Node::IncTapePosUntilEmpty;
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::IncTapePosUntilEmpty, bf);
 
         let bf = Node::DecTapePos(1);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::DecTapePos(1), bf);
 
         let bf = Node::DecTapePosUntilEmpty;
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::DecTapePosUntilEmpty, bf);
 
         let bf = Node::Clear;
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Clear, bf);
 
         let bf = Node::AddToTheRightAndClear(10);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::AddToTheRightAndClear(10), bf);
 
         let bf = Node::DecFromTheRightAndClear(10);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::DecFromTheRightAndClear(10), bf);
 
         let bf = Node::Root(vec![
             Node::Inc(3), Node::Inc(8)
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Inc(11)]), bf);
 
         let bf = Node::Root(vec![
             Node::Dec(3), Node::Dec(8)
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Dec(11)]), bf);
 
         let bf = Node::Root(vec![
             Node::IncTapePos(3), Node::IncTapePos(8)
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::IncTapePos(11)]), bf);
 
         let bf = Node::Root(vec![
             Node::DecTapePos(3), Node::DecTapePos(8)
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::DecTapePos(11)]), bf);
 
         let bf = Node::Root(vec![
             Node::Loop(vec![Node::Inc(3), Node::Inc(8)])
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Loop(vec![Node::Inc(11)])]), bf);
 
         let bf = Node::Root(vec![
             Node::Loop(vec![Node::Dec(3), Node::Dec(8)])
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Loop(vec![Node::Dec(11)])]), bf);
 
         let bf = Node::Root(vec![
             Node::Loop(vec![Node::IncTapePos(3), Node::IncTapePos(8)])
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Loop(vec![Node::IncTapePos(11)]) ]), bf);
 
         let bf = Node::Root(vec![
             Node::Loop(vec![Node::DecTapePos(3), Node::DecTapePos(8)])
         ]);
-        let bf = bf.optimize_series();
+        let bf = bf.optimize_series(false, false);
         assert_eq!(Node::Root(vec![Node::Loop(vec![Node::DecTapePos(11)])]), bf);
 
         let bf = parse_bf("+++++");
@@ -0,0 +1,200 @@
+use crate::Node;
+
+/// Shrinks a program to the tree of its shortest source, which `Display` writes: comments are
+/// dropped, increments and decrements cancel out, as do moves to the right and to the left,
+/// and loops which never run are removed, those starting the program and those following a
+/// loop, which leaves the current cell 0. `Node::Inc(255)` becomes `Node::Dec(1)`.
+///
+/// A loop whose body cancels out never ends once it runs, unless the cell is known to be 0.
+/// Its body is written `+-`, as the parser drops an empty loop. A move out of the tape and back
+/// counts as no move.
+///
+/// In a program forking with `Node::Fork`, another thread can write or read the current cell
+/// between two nodes, so nothing cancels out and no loop is known to leave a cell 0.
+///
+/// Any other node is minified on its own, without knowing the current cell.
+pub fn minify(program: &Node) -> Node {
+    let shared_tape = program.forks();
+    let minified = match program {
+        Node::Root(nodes) => Node::Root(minify_sequence(nodes, true, shared_tape)),
+        Node::Loop(body) => Node::Loop(minify_loop_body(body, shared_tape)),
+        Node::Procedure(body) => Node::Procedure(minify_sequence(body, false, shared_tape)),
+        node => node.clone()
+    };
+    minified.optimize_loops()
+}
+
+/// `zeroed` tells whether the current cell is 0 before the nodes run, `shared_tape` whether
+/// threads share the tape.
+fn minify_sequence(nodes: &[Node], zeroed: bool, shared_tape: bool) -> Vec<Node> {
+    let mut minified = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        match node {
+            Node::Comment { .. } => {}
+            node if shared_tape && cancels(minified.last(), node) => minified.push(node.clone()),
+            Node::Inc(amount) => add(&mut minified, *amount),
+            Node::Dec(amount) => add(&mut minified, amount.wrapping_neg()),
+            Node::IncTapePos(shift) => move_by(&mut minified, *shift as isize),
+            Node::DecTapePos(shift) => move_by(&mut minified, -(*shift as isize)),
+            Node::Loop(body) => {
+                let body = minify_loop_body(body, shared_tape);
+                if !leaves_zero(&minified, zeroed, shared_tape) {
+                    minified.push(Node::Loop(body));
+                }
+            }
+            Node::Procedure(body) => minified.push(Node::Procedure(minify_sequence(body, false, shared_tape))),
+            node if is_loop(node) => {
+                if !leaves_zero(&minified, zeroed, shared_tape) {
+                    minified.push(node.clone());
+                }
+            }
+            node => minified.push(node.clone())
+        }
+    }
+    minified
+}
+
+/// Minifies the body of a loop, which runs on a cell which isn't 0. A body which cancels out
+/// becomes `+-`: like the original, the loop then never ends, while `[]` would parse to no loop
+/// at all.
+fn minify_loop_body(body: &[Node], shared_tape: bool) -> Vec<Node> {
+    match minify_sequence(body, false, shared_tape) {
+        minified if minified.is_empty() => vec![Node::Inc(1), Node::Dec(1)],
+        minified => minified
+    }
+}
+
+/// Adds `amount` to the increment or decrement ending the nodes, if any, writing the total
+/// with the fewest commands.
+fn add(nodes: &mut Vec<Node>, amount: u8) {
+    let last = match nodes.last() {
+        Some(Node::Inc(last)) => Some(*last),
+        Some(Node::Dec(last)) => Some(last.wrapping_neg()),
+        _ => None
+    };
+    if last.is_some() {
+        nodes.pop();
+    }
+    match last.unwrap_or(0).wrapping_add(amount) {
+        0 => {}
+        total @ 1..=128 => nodes.push(Node::Inc(total)),
+        total => nodes.push(Node::Dec(total.wrapping_neg()))
+    }
+}
+
+/// `add` for the moves of the tape position.
+fn move_by(nodes: &mut Vec<Node>, shift: isize) {
+    let last = match nodes.last() {
+        Some(Node::IncTapePos(last)) => Some(*last as isize),
+        Some(Node::DecTapePos(last)) => Some(-(*last as isize)),
+        _ => None
+    };
+    if last.is_some() {
+        nodes.pop();
+    }
+    match last.unwrap_or(0) + shift {
+        0 => {}
+        total @ 1.. => nodes.push(Node::IncTapePos(total as usize)),
+        total => nodes.push(Node::DecTapePos(total.unsigned_abs()))
+    }
+}
+
+/// Whether the node undoes the increment, decrement or move before it, at least partly.
+fn cancels(last: Option<&Node>, node: &Node) -> bool {
+    matches!(
+        (last, node),
+        (Some(Node::Inc(_)), Node::Dec(_))
+            | (Some(Node::Dec(_)), Node::Inc(_))
+            | (Some(Node::IncTapePos(_)), Node::DecTapePos(_))
+            | (Some(Node::DecTapePos(_)), Node::IncTapePos(_))
+    )
+}
+
+/// Whether the node is written as a loop, which only ends once the current cell is 0.
+fn is_loop(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Loop(_)
+            | Node::Clear
+            | Node::IncTapePosUntilEmpty
+            | Node::DecTapePosUntilEmpty
+            | Node::AddToTheRightAndClear(_)
+            | Node::DecFromTheRightAndClear(_)
+            | Node::AddToTheLeftAndClear(_)
+            | Node::DecFromTheLeftAndClear(_)
+    )
+}
+
+/// Whether the current cell is surely 0 after the nodes, run from a cell which is 0 if
+/// `zeroed`. Only the first thread runs before any node, so the start of a program is 0
+/// even if threads share the tape.
+fn leaves_zero(nodes: &[Node], zeroed: bool, shared_tape: bool) -> bool {
+    // Defining a procedure, dumping the tape or storing the cell leave the cell as it is.
+    match nodes.iter().rev().find(|node| !matches!(node, Node::Procedure(_) | Node::Debug | Node::Store)) {
+        Some(node) => !shared_tape && is_loop(node),
+        None => zeroed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Extensions, Node, evaluate, parse_bf, try_parse_bf_with};
+    use super::minify;
+
+    const HELLO: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+    const EXTENSIONS: Extensions = Extensions { debug: true, procedures: true, fork: true, extended: true };
+
+    #[test]
+    fn every_node_is_written_as_it_parses() {
+        let programs = [
+            HELLO,
+            "+[>]<[<]>[-]+[+]>[->>+<<]>[>>-<<-]<[-<<<+>>>]<[<-->-]<[-<->]",
+            "#(++Y):-@$!{}~^&|,.[>,.<-]>>>(++)<<",
+            "-+><"
+        ];
+        for code in programs {
+            let program = try_parse_bf_with(code, EXTENSIONS).unwrap();
+            assert_eq!(Ok(&program), try_parse_bf_with(&program.to_string(), EXTENSIONS).as_ref(), "{code}");
+        }
+        assert_eq!("[-]>[->>+<<]<[-<<<->>>]", parse_bf("[+]>[>>+<<-]<[<<<->>>-]").to_string());
+    }
+
+    #[test]
+    fn minified_programs_are_shorter_and_run_the_same() {
+        let cases = [
+            ("[comment with +-.,<>]+++++--->><<<.", "++<."),
+            ("+-[-]>+<<>>-,[>]", ">,[>]"),
+            ("-[+[-]]<[+]>+[.[>+<-]+-[-<+>]]", "-[+[-]]<[-]>+[.[->+<]]"),
+            ("+(+[-]-+[>])[-]:", "+(+[-])[-]:"),
+            ("+[+-].>[><]", "+[+-].>[+-]"),
+            (">+[><]", ">+[+-]"),
+            ("[+-]+[-][><]", "+[-]"),
+            // another thread may change the cell between two nodes
+            ("Y+-[-][+]>[<]<>", "Y+-[-][-]>[<]<>"),
+            (HELLO, HELLO),
+            ("+++[>+++++<-]>[<++>-]<[->+>+<<]>>[-<<+>>]<<.>>>.", "+++[>+++++<-]>[<++>-]<[->+>+<<]>>[-<<+>>]<<.>>>.")
+        ];
+        for (code, expected) in cases {
+            let program = try_parse_bf_with(code, EXTENSIONS).unwrap();
+            let minified = minify(&program);
+            assert_eq!(expected, minified.to_string(), "{code}");
+            assert_eq!(Ok(&minified), try_parse_bf_with(&minified.to_string(), EXTENSIONS).as_ref(), "{code}");
+            assert!(minified.to_string().len() <= code.len(), "{code}");
+            assert_eq!(evaluate(&program, 16, 10_000), evaluate(&minified, 16, 10_000), "{code}");
+        }
+
+        let decrements = parse_bf(&format!("{}.", "-".repeat(133)));
+        assert_eq!(format!("{}.", "+".repeat(123)), minify(&decrements).to_string());
+    }
+
+    #[test]
+    fn nodes_are_minified_on_their_own() {
+        let program = parse_bf("+[>+<-+-]");
+        let Node::Root(nodes) = &program else { unreachable!() };
+        assert_eq!(Node::Loop(vec![Node::Inc(1), Node::Dec(1)]), minify(&Node::Loop(vec![Node::Inc(2), Node::Dec(2)])));
+        assert_eq!("[->+<]", minify(&nodes[1]).to_string());
+        assert_eq!(Node::Procedure(vec![]), minify(&Node::Procedure(vec![Node::Inc(1), Node::Dec(1)])));
+        assert_eq!(Node::Inc(3), minify(&Node::Inc(3)));
+    }
+}
@@ -1,106 +1,434 @@
+use std::path::PathBuf;
 use std::time::Instant;
-use proc_macro2::{TokenStream};
-use quote::{format_ident, quote};
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{Ident, LitInt, LitStr, Token, Visibility};
 
-use brain_fuck_parser::{Node, parse_bf};
+use brain_fuck_codegen::{Cell, Input, Options, Output};
+use brain_fuck_parser::{Extensions, Node, ParseError, evaluate, partially_evaluate, try_parse_bf_wide, try_parse_bf_with};
 
+/// Steps a `precompute`d program or an `evaluate_prefix` may take by default.
+const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// `bf!(name, "program")` defines `pub fn name()` running the program on stdin and stdout.
+///
+/// A visibility may precede the name, `pub(self)` making the function private. The program
+/// may be followed by comma separated options:
+///
+/// - `input = stdin | slice | reader`: read from stdin, an `input: &[u8]` parameter or
+///   an `input: impl Read` parameter,
+/// - `output = stdout | vec | writer`: print to stdout, return a `Vec<u8>` or write to
+///   an `output: impl Write` parameter,
+/// - `return_tape`: return the final tape too, after the output if that is returned,
+/// - `tape_size = <cells>`: 1 MiB cells by default,
+/// - `cell = u8 | u16 | u32 | u64`: type of the cells,
+/// - `padding = <cells>`: extra cells on both sides of the tape, none by default,
+/// - `unsafe`: access the tape through a raw pointer without bounds checks, making a
+///   program that leaves the tape, padding included, undefined behavior. Programs which
+///   provably stay on the tape, those without scans, calls or loops moving the cell pointer,
+///   get no bounds checks without it,
+/// - `precompute` or `precompute = <steps>`: run the program during expansion, taking up to
+///   10 million steps by default, and if it finishes without reading input, make the function
+///   just produce its output and tape. Only available with 8-bit cells,
+/// - `evaluate_prefix` or `evaluate_prefix = <steps>`: run what comes before the first input
+///   during expansion, with the same limit, and replace it with code setting up the tape and
+///   printing its output, see `partially_evaluate`. Only available with 8-bit cells,
+/// - `extensions = "debug"`: comma separated language extensions, `debug` making `#` dump
+///   the tape position and the cells around it to stderr, `pbrain` adding the `(`, `)` and
+///   `:` procedures and `ebf` Extended Brainfuck Type I's `@` end, `$` and `!` storage
+///   register and `{`, `}`, `~`, `^`, `&` and `|` bit operations,
+/// - `quiet`: don't print the code generation time when the function is called.
+///
+/// With a reader or a writer the function returns `std::io::Result`. E.g.
+///
+/// ```ignore
+/// bf!(pub(crate) echo, ",+[-.,+]", input = slice, output = vec, quiet);
+/// assert_eq!(b"hi".to_vec(), echo(b"hi"));
+/// ```
+///
+/// Malformed invocations and BF syntax errors are reported as compile errors; for the latter
+/// the span is narrowed down to the offending character where the compiler supports it.
 #[proc_macro]
 pub fn bf(items: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let mut items_iter = items.into_iter();
+    expand(items.into()).into()
+}
 
-    let function_name = match items_iter.next() {
-        Some(proc_macro::TokenTree::Ident(ident)) => ident,
-        _ => panic!("expected identifier")
-    };
-    let foo_name = format_ident!("{}", function_name.to_string());
+/// `include_bf!(name, "path/to/program.b")` is `bf!` with the program read from a file,
+/// relative to the directory of the invoking crate's `Cargo.toml`, taking the same options.
+/// The crate is rebuilt when the file changes.
+#[proc_macro]
+pub fn include_bf(items: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    expand_include(items.into()).into()
+}
+
+fn expand(items: TokenStream) -> TokenStream {
+    let result = syn::parse2::<BfInput>(items).and_then(|mut input| {
+        let instant = Instant::now();
+        let program = parse_program(&input)?;
+        check_extent(&mut input, &program)?;
+        Ok(generate(&input, &program, instant))
+    });
+    result.unwrap_or_else(|err| err.to_compile_error())
+}
+
+fn expand_include(items: TokenStream) -> TokenStream {
+    let result = syn::parse2::<BfInput>(items).and_then(|mut input| {
+        let instant = Instant::now();
+        let path = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default())
+            .join(input.code.value());
+        let source = std::fs::read_to_string(&path).map_err(|err| {
+            syn::Error::new(input.code.span(), format!("failed to read {}: {err}", path.display()))
+        })?;
+        let program = parse_source(&source, &input).map_err(|err| {
+            syn::Error::new(input.code.span(), format!("{}: {}", path.display(), describe(&source, err)))
+        })?;
+        check_extent(&mut input, &program)?;
+        let function = generate(&input, &program, instant);
+        // Makes cargo track the file, so editing it triggers a rebuild.
+        let path = path.to_string_lossy();
+        Ok(quote!(
+            const _: &[u8] = include_bytes!(#path);
+            #function
+        ))
+    });
+    result.unwrap_or_else(|err| err.to_compile_error())
+}
+
+/// Arguments of `bf!` and `include_bf!`, the string being the program or the path to it.
+struct BfInput {
+    visibility: Visibility,
+    name: Ident,
+    code: LitStr,
+    options: Options,
+    extensions: Extensions,
+    /// Steps the program may take during expansion, if it is to be precomputed.
+    precompute: Option<u64>,
+    /// Steps the part before the first input may take during expansion, if it is to be evaluated.
+    evaluate_prefix: Option<u64>,
+    quiet: bool
+}
 
-    match items_iter.next() {
-        Some(proc_macro::TokenTree::Punct(punct)) => {
-            if punct.as_char() != ',' {
-                panic!("expected ,")
+impl Parse for BfInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let visibility = input.parse()?;
+        let name = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let code = input.parse()?;
+        let mut parsed = Self {
+            visibility,
+            name,
+            code,
+            options: Options::default(),
+            extensions: Extensions::default(),
+            precompute: None,
+            evaluate_prefix: None,
+            quiet: false
+        };
+        let mut seen = Vec::new();
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
             }
-        },
-        _ => panic!("expected punct")
-    };
+            let key = if input.peek(Token![unsafe]) {
+                Ident::new("unsafe", input.parse::<Token![unsafe]>()?.span)
+            } else {
+                input.parse()?
+            };
+            if seen.contains(&key) {
+                return Err(syn::Error::new(key.span(), format!("duplicate option `{key}`")));
+            }
+            parsed.parse_option(&key, input)?;
+            seen.push(key);
+        }
+        if let Some(key) = seen.iter().find(|key| *key == "precompute" || *key == "evaluate_prefix") {
+            if parsed.options.cell != Cell::U8 {
+                return Err(syn::Error::new(key.span(), format!("`{key}` needs 8-bit cells")));
+            }
+        }
+        Ok(parsed)
+    }
+}
 
-    let literal = match items_iter.next() {
-        Some(proc_macro::TokenTree::Literal(literal)) => {
-            literal.to_string()
-        },
-        _ => panic!("expected literal")
-    };
+impl BfInput {
+    fn parse_option(&mut self, key: &Ident, input: ParseStream) -> syn::Result<()> {
+        match key.to_string().as_str() {
+            "input" => {
+                self.options.input = match value(input, &["stdin", "slice", "reader"])? {
+                    "stdin" => Input::Stdin,
+                    "slice" => Input::Slice,
+                    _ => Input::Reader
+                }
+            }
+            "output" => {
+                self.options.output = match value(input, &["stdout", "vec", "writer"])? {
+                    "stdout" => Output::Stdout,
+                    "vec" => Output::Vec,
+                    _ => Output::Writer
+                }
+            }
+            "cell" => {
+                self.options.cell = match value(input, &["u8", "u16", "u32", "u64"])? {
+                    "u8" => Cell::U8,
+                    "u16" => Cell::U16,
+                    "u32" => Cell::U32,
+                    _ => Cell::U64
+                }
+            }
+            "tape_size" => {
+                input.parse::<Token![=]>()?;
+                let size: LitInt = input.parse()?;
+                self.options.tape_size = size.base10_parse()?;
+                if self.options.tape_size == 0 {
+                    return Err(syn::Error::new(size.span(), "the tape needs at least one cell"));
+                }
+            }
+            "padding" => {
+                input.parse::<Token![=]>()?;
+                self.options.padding = input.parse::<LitInt>()?.base10_parse()?;
+            }
+            "unsafe" => self.options.unchecked = true,
+            "precompute" => self.precompute = Some(fuel(input)?),
+            "evaluate_prefix" => self.evaluate_prefix = Some(fuel(input)?),
+            "extensions" => {
+                input.parse::<Token![=]>()?;
+                let names: LitStr = input.parse()?;
+                self.extensions = names.value().parse().map_err(|err| syn::Error::new(names.span(), err))?;
+                if self.extensions.fork {
+                    return Err(syn::Error::new(names.span(), "`brainfork` threads can't be compiled to Rust"));
+                }
+            }
+            "return_tape" => self.options.return_tape = true,
+            "quiet" => self.quiet = true,
+            _ => {
+                return Err(syn::Error::new(key.span(), format!(
+                    "unknown option `{key}`, expected one of: input, output, return_tape, tape_size, cell, padding, unsafe, precompute, evaluate_prefix, extensions, quiet"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
 
-    let instant = Instant::now();
-    let statements = if literal.starts_with('\"') {
-        parse_bf(literal.trim_matches('\"')).to_token_stream()
-    } else if literal.starts_with("r#") {
-        parse_bf((&literal[1..]).trim_matches('#').trim_matches('\"')).to_token_stream()
-    } else {
-        panic!("expected string literal");
+/// Parses an optional `= steps`, `DEFAULT_FUEL` if missing.
+fn fuel(input: ParseStream) -> syn::Result<u64> {
+    if input.parse::<Option<Token![=]>>()?.is_none() {
+        return Ok(DEFAULT_FUEL);
+    }
+    input.parse::<LitInt>()?.base10_parse()
+}
+
+/// Parses `= value` where the value is one of `allowed`.
+fn value(input: ParseStream, allowed: &[&'static str]) -> syn::Result<&'static str> {
+    input.parse::<Token![=]>()?;
+    let value: Ident = input.parse()?;
+    allowed.iter().copied().find(|allowed| value == allowed).ok_or_else(|| {
+        syn::Error::new(value.span(), format!("expected one of: {}", allowed.join(", ")))
+    })
+}
+
+/// Rejects unchecked programs that provably leave the tape, which would be undefined behavior,
+/// and makes those provably staying on it unchecked.
+fn check_extent(input: &mut BfInput, program: &Node) -> syn::Result<()> {
+    let options = &mut input.options;
+    match brain_fuck_codegen::tape_extent(program) {
+        Some(extent) if options.contains(&extent) => {
+            options.unchecked = true;
+            Ok(())
+        }
+        Some(extent) if options.unchecked => Err(syn::Error::new(
+            input.code.span(),
+            format!(
+                "the program touches cells {} to {} relative to the first one, outside of the tape \
+                 with `unsafe`; make the tape or the padding larger",
+                extent.start(), extent.end()
+            )
+        )),
+        _ => Ok(())
+    }
+}
+
+/// The function running the program, `instant` being when code generation started.
+fn generate(input: &BfInput, program: &Node, instant: Instant) -> TokenStream {
+    let name = &input.name;
+    let visibility = match &input.visibility {
+        Visibility::Inherited => quote!(pub),
+        visibility => quote!(#visibility)
     };
+    let options = &input.options;
+    let parameters = brain_fuck_codegen::parameters(options);
+    let return_type = brain_fuck_codegen::return_type(options);
+    let evaluation = input.precompute.and_then(|fuel| evaluate(program, options.tape_size, fuel));
+    let body = match (evaluation, input.evaluate_prefix) {
+        (Some(evaluation), _) => brain_fuck_codegen::precomputed_body(&evaluation, options),
+        (None, Some(fuel)) => {
+            let program = partially_evaluate(program, options.tape_size, fuel);
+            brain_fuck_codegen::function_body(&program, options)
+        }
+        (None, None) => brain_fuck_codegen::function_body(program, options)
+    };
+    let return_value = brain_fuck_codegen::return_value(options);
     let codegen_time = instant.elapsed().as_secs_f32();
-
-    proc_macro::TokenStream::from(quote!(
-        pub fn #foo_name() {
-            let mut tape: Vec<u8> = vec![0; 0x100000];
-            let mut tape_pos = 0;
-            #statements
+    let timing = if input.quiet {
+        quote!()
+    } else {
+        quote!(
             let codegen_time = #codegen_time;
             println!("code generation time: {} seconds", codegen_time);
+        )
+    };
+
+    quote!(
+        #[allow(unused_mut, unused_variables, unused_unsafe, unused_labels, unreachable_code)]
+        #visibility fn #name(#parameters) #return_type {
+            #body
+            #timing
+            #return_value
         }
-    ))
-}
-
-trait ToTokenStream {
-    fn to_token_stream(&self) -> proc_macro2::TokenStream;
-}
-
-impl ToTokenStream for Node {
-    fn to_token_stream(&self) -> proc_macro2::TokenStream {
-        match self {
-            Node::Root(nodes) => nodes.iter().map(|node| node.to_token_stream()).collect(),
-            Node::Inc(inc_amount) => quote!(tape[tape_pos] += #inc_amount;),
-            Node::Dec(dec_amount) => quote!(tape[tape_pos] -= #dec_amount;),
-            Node::IncTapePos(inc_amount) => quote!(tape_pos += #inc_amount;),
-            Node::DecTapePos(dec_amount) => quote!(tape_pos -= #dec_amount;),
-            Node::IncTapePosUntilEmpty => quote!( while tape[tape_pos] != 0 { tape_pos += 1; }),
-            Node::DecTapePosUntilEmpty => quote!( while tape[tape_pos] != 0 { tape_pos -= 1; }),
-            Node::PutChar => quote!(print!("{}", tape[tape_pos] as char);),
-            Node::GetChar => quote!(tape[tape_pos] = unsafe { libc::getchar() } as u8;),
-            Node::Clear => quote!(tape[tape_pos] = 0;),
-            Node::AddToTheRightAndClear(offset) => quote!(
-                tape[tape_pos + #offset] += tape[tape_pos];
-                tape[tape_pos] = 0;
-            ),
-            Node::DecFromTheRightAndClear(offset) => quote!(
-                tape[tape_pos + #offset] -= tape[tape_pos];
-                tape[tape_pos] = 0;
-            ),
-            Node::AddToTheLeftAndClear(offset) => quote!(
-                if tape[tape_pos] != 0 {
-                    tape[tape_pos - #offset] += tape[tape_pos];
-                    tape[tape_pos] = 0;
-                }
-            ),
-            Node::DecFromTheLeftAndClear(offset) => quote!(
-                if tape[tape_pos] != 0 {
-                    tape[tape_pos - #offset] -= tape[tape_pos];
-                    tape[tape_pos] = 0;
-                }
-            ),
-            Node::Loop(nodes) => {
-                let statements: TokenStream = nodes
-                    .iter()
-                    .map(|node| node.to_token_stream())
-                    .collect();
-
-                quote!(
-                    while tape[tape_pos] != 0 {
-                        #statements
-                    }
-                )
-            },
-            Node::Comment => unreachable!(),
-        }
+    )
+}
+
+fn parse_program(input: &BfInput) -> syn::Result<Node> {
+    let code = &input.code;
+    let source = code.value();
+    parse_source(&source, input).map_err(|err| {
+        let span = character_span(code, &source, err.position).unwrap_or_else(|| code.span());
+        syn::Error::new(span, describe(&source, err))
+    })
+}
+
+/// Parses the program, keeping runs of more than 255 `+` or `-` whole for cells wider than a
+/// byte.
+fn parse_source(source: &str, input: &BfInput) -> Result<Node, ParseError> {
+    match input.options.cell {
+        Cell::U8 => try_parse_bf_with(source, input.extensions),
+        _ => try_parse_bf_wide(source, input.extensions)
+    }
+}
+
+/// The error with the line and column of the offending character, counted from 1.
+fn describe(source: &str, err: ParseError) -> String {
+    let before = &source[..err.position];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    format!("{err} (line {line}, column {column} of the program)")
+}
+
+/// Span of the character at `position` of the literal's value. Only available when the
+/// compiler supports subspans, and when the literal has no escapes, which would make
+/// offsets in the value and in the source differ.
+fn character_span(code: &LitStr, source: &str, position: usize) -> Option<Span> {
+    let literal = code.token();
+    let text = literal.to_string();
+    let opening_quote = text.find('"')?;
+    if !text.starts_with('r') && text.contains('\\') {
+        return None;
+    }
+    let start = opening_quote + 1 + position;
+    let width = source[position..].chars().next()?.len_utf8();
+    literal.subspan(start..start + width)
+}
+
+#[cfg(test)]
+mod tests {
+    use quote::quote;
+    use super::{expand, expand_include};
+
+    fn compile_error(tokens: proc_macro2::TokenStream) -> String {
+        let expanded = expand(tokens).to_string();
+        assert!(expanded.starts_with(":: core :: compile_error !"), "{expanded}");
+        expanded
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn expands_to_a_function() {
+        let expanded = expand(quote!(hello, "+.")).to_string();
+        assert!(expanded.contains("pub fn hello () {"), "{expanded}");
+        assert!(!expand(quote!(hello, r#"+."#,)).to_string().contains("compile_error"));
+    }
+
+    #[test]
+    fn malformed_invocations_are_compile_errors() {
+        assert!(compile_error(quote!("+.")).contains("expected identifier"));
+        assert!(compile_error(quote!(hello "+.")).contains("expected `,`"));
+        assert!(compile_error(quote!(hello, 42)).contains("expected string literal"));
+        assert!(compile_error(quote!(hello, "+.", extra)).contains("unknown option `extra`"));
+        assert!(compile_error(quote!(hello, "+.", input = file)).contains("expected one of: stdin, slice, reader"));
+        assert!(compile_error(quote!(hello, "+.", quiet, quiet)).contains("duplicate option `quiet`"));
+        assert!(compile_error(quote!(hello, "+.", tape_size = 0)).contains("at least one cell"));
+        assert!(compile_error(quote!(hello, "+.", unsafe, unsafe)).contains("duplicate option `unsafe`"));
+    }
+
+    #[test]
+    fn unchecked_programs_leaving_the_tape_are_rejected() {
+        assert!(compile_error(quote!(hello, "<+", unsafe)).contains("touches cells -1 to 0"));
+        assert!(compile_error(quote!(hello, ">>>+", unsafe, tape_size = 3)).contains("touches cells 0 to 3"));
+        assert!(!expand(quote!(hello, "<+", unsafe, padding = 1)).to_string().contains("compile_error"));
+        // Without `unsafe`, leaving the tape panics instead.
+        assert!(!expand(quote!(hello, "<+")).to_string().contains("compile_error"));
+        // Scans may go anywhere, so they are accepted.
+        assert!(!expand(quote!(hello, "[<]", unsafe)).to_string().contains("compile_error"));
+    }
+
+    #[test]
+    fn programs_staying_on_the_tape_are_unchecked() {
+        assert!(expand(quote!(hello, "+[->>+<<]>>.", tape_size = 3)).to_string().contains("as_mut_ptr"));
+        assert!(expand(quote!(hello, "<+", padding = 1)).to_string().contains("as_mut_ptr"));
+        assert!(expand(quote!(hello, ">>>+", tape_size = 3)).to_string().contains("tape_pos"));
+        assert!(expand(quote!(hello, "+[>]")).to_string().contains("tape_pos"));
+    }
+
+    #[test]
+    fn precomputed_programs_produce_their_output() {
+        let expanded = expand(quote!(hello, "++++++[>++++++++++++<-]>.", precompute, quiet)).to_string();
+        assert!(expanded.contains("write_all (& mut std :: io :: stdout () , b\"H\")"), "{expanded}");
+        assert!(!expanded.contains("tape_pos"), "{expanded}");
+        // Reading input or running out of fuel falls back to running the program.
+        assert!(expand(quote!(hello, ",.", precompute)).to_string().contains("getchar"));
+        assert!(expand(quote!(hello, "+[>+<]", precompute = 1000)).to_string().contains("while"));
+        assert!(compile_error(quote!(hello, "+.", cell = u16, precompute)).contains("`precompute` needs 8-bit cells"));
+    }
+
+    #[test]
+    fn evaluated_prefix_is_straight_line_code() {
+        let expanded = expand(quote!(hello, "++++++[>++++++++++++<-]>.,.", evaluate_prefix, quiet)).to_string();
+        assert!(!expanded.contains("while"), "{expanded}");
+        assert!(expanded.contains("wrapping_add (72)"), "{expanded}");
+        assert!(compile_error(quote!(hello, "+.", cell = u64, evaluate_prefix = 10)).contains("`evaluate_prefix` needs 8-bit cells"));
+    }
+
+    #[test]
+    fn extensions_are_opt_in() {
+        assert!(!expand(quote!(hello, "+#", quiet)).to_string().contains("eprintln"));
+        assert!(expand(quote!(hello, "+#", extensions = "debug", quiet)).to_string().contains("eprintln"));
+        assert!(compile_error(quote!(hello, "+#", extensions = "debug,ook")).contains("unknown extension ook"));
+        assert!(compile_error(quote!(hello, "+Y", extensions = "brainfork")).contains("threads can't be compiled"));
+    }
+
+    #[test]
+    fn syntax_errors_point_into_the_program() {
+        assert!(compile_error(quote!(hello, "+[.")).contains("unmatched '[' at 1 (line 1, column 2 of the program)"));
+        assert!(compile_error(quote!(hello, "+\n  ]")).contains("unmatched ']' at 4 (line 2, column 3 of the program)"));
+    }
+
+    #[test]
+    fn included_program_is_tracked() {
+        let expanded = expand_include(quote!(hello, "../benches/corpus/hello.b")).to_string();
+        assert!(expanded.starts_with("const _ : & [u8] = include_bytes ! ("), "{expanded}");
+        assert!(expanded.contains("hello.b\") ; # [allow (unused_mut , unused_variables , unused_unsafe , unused_labels , unreachable_code)] pub fn hello ()"), "{expanded}");
+    }
+
+    #[test]
+    fn included_program_errors_name_the_file() {
+        let expanded = expand_include(quote!(hello, "missing.b")).to_string();
+        assert!(expanded.contains("failed to read ") && expanded.contains("missing.b"), "{expanded}");
+
+        let path = std::env::temp_dir().join(format!("bf-include-{}.b", std::process::id()));
+        std::fs::write(&path, "+\n[").unwrap();
+        let path_str = path.to_str().unwrap();
+        let expanded = expand_include(quote!(hello, #path_str)).to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(expanded.contains(&format!("{path_str}: unmatched '[' at 2 (line 2, column 1 of the program)")), "{expanded}");
+    }
+}
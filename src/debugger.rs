@@ -0,0 +1,145 @@
+use std::io::{Read, Stdout, Write};
+
+use crate::{Config, Error, Machine, Status};
+use crate::history::History;
+use crate::machine::debug_window;
+
+const RECORD_CAPACITY: usize = 0x100000;
+const SNAPSHOT_INTERVAL: u64 = 0x100000;
+const SNAPSHOT_CAPACITY: usize = 64;
+
+const HELP: &str = "\
+commands:
+  s [n]       step forward n instructions (default 1)
+  b [n]       step back n instructions (default 1)
+  c           continue until the program ends or faults
+  rc          reverse continue to the start of the recorded history
+  rw <cell>   step back until the instruction which last wrote to the cell
+  p           print the machine state
+  q           quit";
+
+enum State {
+    Running,
+    Finished
+}
+
+struct Session {
+    ctx: Machine<Box<dyn Read>, Stdout>,
+    history: History,
+    state: State
+}
+
+impl Session {
+    fn step(&mut self, count: u64) {
+        for _ in 0..count {
+            if let State::Finished = self.state {
+                println!("program has finished");
+                return;
+            }
+            match self.history.step(&mut self.ctx) {
+                Ok(Status::Running) => {}
+                Ok(Status::Finished) => self.state = State::Finished,
+                Err(fault) => {
+                    println!("fault: {fault}");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn step_back(&mut self, count: u64) {
+        let mut remaining = count;
+        while remaining > 0 {
+            match self.history.step_back(&mut self.ctx) {
+                Some(gone_back) => remaining = remaining.saturating_sub(gone_back),
+                None => {
+                    println!("reached the start of the recorded history");
+                    break;
+                }
+            }
+            self.state = State::Running;
+        }
+    }
+
+    fn reverse_watch(&mut self, cell: usize) {
+        if cell >= self.ctx.tape.len() {
+            println!("cell {cell} is out of the tape");
+            return;
+        }
+        let value = self.ctx.tape[cell];
+        while self.ctx.tape[cell] == value {
+            if self.history.step_back(&mut self.ctx).is_none() {
+                println!("cell {cell} was not written within the recorded history");
+                return;
+            }
+            self.state = State::Running;
+        }
+    }
+
+    fn print(&self) {
+        let ip = self.ctx.instruction_pointer;
+        let op = self.ctx.current_operation();
+        println!("step: {}, ip: {}, op: {:?}", self.history.current_step(), ip, op);
+        println!("tape_pos: {}, ip_stack: {:?}", self.ctx.tape_pos, self.ctx.ip_stack);
+        if !self.ctx.call_stack.is_empty() {
+            println!("call_stack: {:?}", self.ctx.call_stack);
+        }
+        if self.ctx.threads() > 1 {
+            println!("thread: {} ({} running)", self.ctx.thread(), self.ctx.threads());
+        }
+        if self.ctx.storage != 0 {
+            println!("storage: {}", self.ctx.storage);
+        }
+        for pos in debug_window(&self.ctx.tape, self.ctx.tape_pos) {
+            let marker = if pos == self.ctx.tape_pos { '*' } else { ' ' };
+            print!("{marker}{pos}:{} ", self.ctx.tape[pos]);
+        }
+        println!();
+    }
+}
+
+/// Runs an interactive debugger session on stdin, which allows stepping the program
+/// both forward and backward. The program shares stdin with the debugger commands.
+pub fn debug(code: &str, config: Config) -> Result<(), Error> {
+    debug_with_input(code, config, std::io::stdin())
+}
+
+/// `debug` with the program reading from `input` rather than sharing stdin.
+pub fn debug_with_input(code: &str, config: Config, input: impl Read + 'static) -> Result<(), Error> {
+    let ctx = Machine::parse(code, config, Box::new(input) as Box<dyn Read>, std::io::stdout())?;
+    let mut session = Session {
+        ctx,
+        history: History::new(RECORD_CAPACITY, SNAPSHOT_INTERVAL, SNAPSHOT_CAPACITY),
+        state: State::Running
+    };
+    println!("{HELP}");
+
+    loop {
+        print!("(bf) ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("s");
+        let argument = words.next().and_then(|it| it.parse::<u64>().ok());
+        match command {
+            "s" => session.step(argument.unwrap_or(1)),
+            "b" => session.step_back(argument.unwrap_or(1)),
+            "c" => session.step(u64::MAX),
+            "rc" => session.step_back(u64::MAX),
+            "rw" => match argument {
+                Some(cell) => session.reverse_watch(cell as usize),
+                None => println!("usage: rw <cell>")
+            },
+            "p" => {}
+            "q" => return Ok(()),
+            _ => {
+                println!("{HELP}");
+                continue;
+            }
+        }
+        session.print();
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt::Write;
+use brain_fuck_parser::Node;
+
+use crate::{Config, Error};
+use super::check_supported;
+
+/// Translates the optimized tree into x86-64 Linux assembly in GNU as syntax, which
+/// assembles and links into a static executable without libc:
+///
+/// ```text
+/// as -o prog.o prog.s && ld -o prog prog.o
+/// ```
+///
+/// The tape lives in `.bss` and `%rbx` points to the current cell. Input and output are
+/// unbuffered `read`/`write` syscalls on stdin and stdout through two small subroutines;
+/// `,` stores 255 on EOF, same as `EOF_VALUE`. Tape bounds are not checked, and
+/// `Node::Debug` is compiled to nothing. Procedures are subroutines defined in place and
+/// jumped over, their addresses stored in a table; calling an undefined one exits with
+/// status 1. The storage register is a byte in `.bss`, and `Node::End` exits in place.
+pub fn emit_asm(program: &Node, config: &Config) -> Result<String, Error> {
+    check_supported(program, "asm")?;
+    let mut out = String::new();
+    writeln!(out, "    .bss").unwrap();
+    writeln!(out, "    .lcomm tape, {}", config.tape_size).unwrap();
+    if program.uses_procedures() {
+        writeln!(out, "    .lcomm procedures, {}", 256 * 8).unwrap();
+    }
+    if program.uses_storage() {
+        writeln!(out, "    .lcomm storage, 1").unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "    .text").unwrap();
+    writeln!(out, "    .globl _start").unwrap();
+    writeln!(out, "_start:").unwrap();
+    writeln!(out, "    leaq tape(%rip), %rbx").unwrap();
+    program.write_asm(&mut out, &mut 0);
+    out.push_str(RUNTIME);
+    if program.uses_procedures() {
+        out.push_str(UNDEFINED_PROCEDURE);
+    }
+    Ok(out)
+}
+
+/// Exit syscall ending the program and the i/o subroutines, which clobber
+/// `%rax`, `%rdi`, `%rsi`, `%rdx`, `%rcx` and `%r11`.
+const RUNTIME: &str = "    movl $60, %eax
+    xorl %edi, %edi
+    syscall
+
+putchar:
+    movl $1, %eax
+    movl $1, %edi
+    movq %rbx, %rsi
+    movl $1, %edx
+    syscall
+    ret
+
+getchar:
+    xorl %eax, %eax
+    xorl %edi, %edi
+    movq %rbx, %rsi
+    movl $1, %edx
+    syscall
+    testq %rax, %rax
+    jg 1f
+    movb $-1, (%rbx)
+1:
+    ret
+";
+
+/// Exit with status 1, where calls to undefined procedures jump.
+const UNDEFINED_PROCEDURE: &str = "
+undefined_procedure:
+    movl $60, %eax
+    movl $1, %edi
+    syscall
+";
+
+trait ToAsm {
+    /// Writes the instructions, numbering loop labels from `labels`.
+    fn write_asm(&self, out: &mut String, labels: &mut usize);
+}
+
+fn next_label(labels: &mut usize) -> usize {
+    *labels += 1;
+    *labels - 1
+}
+
+/// Moves `%rbx` by one cell with `step` (`incq` or `decq`) until it points to an empty cell.
+fn scan(out: &mut String, labels: &mut usize, step: &str) {
+    let label = next_label(labels);
+    writeln!(out, ".Lscan{label}:").unwrap();
+    writeln!(out, "    cmpb $0, (%rbx)").unwrap();
+    writeln!(out, "    je .Lscan{label}_end").unwrap();
+    writeln!(out, "    {step} %rbx").unwrap();
+    writeln!(out, "    jmp .Lscan{label}").unwrap();
+    writeln!(out, ".Lscan{label}_end:").unwrap();
+}
+
+/// Combines (`op` = `xorb`, `andb` or `orb`) the storage register into the current cell.
+fn combine_with_storage(out: &mut String, op: &str) {
+    writeln!(out, "    movb storage(%rip), %al").unwrap();
+    writeln!(out, "    {op} %al, (%rbx)").unwrap();
+}
+
+/// Adds (`op` = `addb`) or subtracts (`subb`) the current cell to or from the one at
+/// `displacement` bytes from it, then clears the current cell.
+fn transfer(out: &mut String, displacement: isize, op: &str) {
+    writeln!(out, "    movb (%rbx), %al").unwrap();
+    writeln!(out, "    {op} %al, {displacement}(%rbx)").unwrap();
+    writeln!(out, "    movb $0, (%rbx)").unwrap();
+}
+
+/// Same as `transfer`, but only touching the other cell if the current one isn't empty,
+/// so a program leaving the tape on the left with an empty cell stays well-defined.
+fn transfer_if_nonzero(out: &mut String, labels: &mut usize, displacement: isize, op: &str) {
+    let label = next_label(labels);
+    writeln!(out, "    cmpb $0, (%rbx)").unwrap();
+    writeln!(out, "    je .Lskip{label}").unwrap();
+    transfer(out, displacement, op);
+    writeln!(out, ".Lskip{label}:").unwrap();
+}
+
+impl ToAsm for Node {
+    fn write_asm(&self, out: &mut String, labels: &mut usize) {
+        match self {
+            Node::Root(nodes) => nodes.iter().for_each(|node| node.write_asm(out, labels)),
+            Node::Inc(amount) => writeln!(out, "    addb ${amount}, (%rbx)").unwrap(),
+            Node::Dec(amount) => writeln!(out, "    subb ${amount}, (%rbx)").unwrap(),
+            Node::IncTapePos(offset) => writeln!(out, "    addq ${offset}, %rbx").unwrap(),
+            Node::DecTapePos(offset) => writeln!(out, "    subq ${offset}, %rbx").unwrap(),
+            Node::IncTapePosUntilEmpty => scan(out, labels, "incq"),
+            Node::DecTapePosUntilEmpty => scan(out, labels, "decq"),
+            Node::PutChar => writeln!(out, "    call putchar").unwrap(),
+            Node::GetChar => writeln!(out, "    call getchar").unwrap(),
+            Node::Clear => writeln!(out, "    movb $0, (%rbx)").unwrap(),
+            Node::AddToTheRightAndClear(offset) => transfer(out, *offset as isize, "addb"),
+            Node::DecFromTheRightAndClear(offset) => transfer(out, *offset as isize, "subb"),
+            Node::AddToTheLeftAndClear(offset) => {
+                transfer_if_nonzero(out, labels, -(*offset as isize), "addb")
+            }
+            Node::DecFromTheLeftAndClear(offset) => {
+                transfer_if_nonzero(out, labels, -(*offset as isize), "subb")
+            }
+            Node::Debug => {}
+            Node::Procedure(nodes) => {
+                let label = next_label(labels);
+                writeln!(out, "    jmp .Lproc{label}_end").unwrap();
+                writeln!(out, ".Lproc{label}:").unwrap();
+                nodes.iter().for_each(|node| node.write_asm(out, labels));
+                writeln!(out, "    ret").unwrap();
+                writeln!(out, ".Lproc{label}_end:").unwrap();
+                writeln!(out, "    movzbl (%rbx), %eax").unwrap();
+                writeln!(out, "    leaq .Lproc{label}(%rip), %rcx").unwrap();
+                writeln!(out, "    leaq procedures(%rip), %rdx").unwrap();
+                writeln!(out, "    movq %rcx, (%rdx,%rax,8)").unwrap();
+            }
+            Node::Call => {
+                writeln!(out, "    movzbl (%rbx), %eax").unwrap();
+                writeln!(out, "    leaq procedures(%rip), %rdx").unwrap();
+                writeln!(out, "    movq (%rdx,%rax,8), %rax").unwrap();
+                writeln!(out, "    testq %rax, %rax").unwrap();
+                writeln!(out, "    jz undefined_procedure").unwrap();
+                writeln!(out, "    call *%rax").unwrap();
+            }
+            Node::Loop(nodes) => {
+                let label = next_label(labels);
+                writeln!(out, "    cmpb $0, (%rbx)").unwrap();
+                writeln!(out, "    je .Lloop{label}_end").unwrap();
+                writeln!(out, ".Lloop{label}:").unwrap();
+                nodes.iter().for_each(|node| node.write_asm(out, labels));
+                writeln!(out, "    cmpb $0, (%rbx)").unwrap();
+                writeln!(out, "    jne .Lloop{label}").unwrap();
+                writeln!(out, ".Lloop{label}_end:").unwrap();
+            }
+            Node::Fork => unreachable!(),
+            Node::End => {
+                writeln!(out, "    movl $60, %eax").unwrap();
+                writeln!(out, "    xorl %edi, %edi").unwrap();
+                writeln!(out, "    syscall").unwrap();
+            }
+            Node::Store => {
+                writeln!(out, "    movb (%rbx), %al").unwrap();
+                writeln!(out, "    movb %al, storage(%rip)").unwrap();
+            }
+            Node::Retrieve => {
+                writeln!(out, "    movb storage(%rip), %al").unwrap();
+                writeln!(out, "    movb %al, (%rbx)").unwrap();
+            }
+            Node::ShiftLeft => writeln!(out, "    shlb $1, (%rbx)").unwrap(),
+            Node::ShiftRight => writeln!(out, "    shrb $1, (%rbx)").unwrap(),
+            Node::Not => writeln!(out, "    notb (%rbx)").unwrap(),
+            Node::Xor => combine_with_storage(out, "xorb"),
+            Node::And => combine_with_storage(out, "andb"),
+            Node::Or => combine_with_storage(out, "orb"),
+            Node::Comment { .. } => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brain_fuck_parser::{Extensions, parse_bf, try_parse_bf_with};
+    use crate::Config;
+    use super::emit_asm;
+
+    #[test]
+    fn emits_loops_and_optimized_nodes() {
+        let config = Config { tape_size: 16, ..Config::default() };
+        let asm = emit_asm(&parse_bf("++[->+<]>[<]>.,[-<->]>[>+.<-]"), &config).unwrap();
+        assert!(asm.starts_with("    .bss
+    .lcomm tape, 16
+
+    .text
+    .globl _start
+_start:
+    leaq tape(%rip), %rbx
+    addb $2, (%rbx)
+    movb (%rbx), %al
+    addb %al, 1(%rbx)
+    movb $0, (%rbx)
+    addq $1, %rbx
+.Lscan0:
+    cmpb $0, (%rbx)
+    je .Lscan0_end
+    decq %rbx
+    jmp .Lscan0
+.Lscan0_end:
+    addq $1, %rbx
+    call putchar
+    call getchar
+    cmpb $0, (%rbx)
+    je .Lskip1
+    movb (%rbx), %al
+    subb %al, -1(%rbx)
+    movb $0, (%rbx)
+.Lskip1:
+    addq $1, %rbx
+    cmpb $0, (%rbx)
+    je .Lloop2_end
+.Lloop2:
+    addq $1, %rbx
+    addb $1, (%rbx)
+    call putchar
+    subq $1, %rbx
+    subb $1, (%rbx)
+    cmpb $0, (%rbx)
+    jne .Lloop2
+.Lloop2_end:
+    movl $60, %eax
+"));
+    }
+
+    /// Assembles and links the emitted program with the system toolchain, if there is one.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn linked_program_runs() {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let dir = std::env::temp_dir().join(format!("bf-emit-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (source, object, binary) = (dir.join("hello.s"), dir.join("hello.o"), dir.join("hello"));
+        let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,+[-.,+]\
+            >>>(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:++++{$>+++^~}.<-!{|.&.+(.@):+.";
+        let program = try_parse_bf_with(code, Extensions { procedures: true, extended: true, ..Extensions::default() }).unwrap();
+        std::fs::write(&source, emit_asm(&program, &Config::default()).unwrap()).unwrap();
+        let assembled = Command::new("as").arg("-o").arg(&object).arg(&source).status();
+        let linked = Command::new("ld").arg("-o").arg(&binary).arg(&object).status();
+        if !matches!((assembled, linked), (Ok(a), Ok(l)) if a.success() && l.success()) {
+            eprintln!("no working assembler and linker, skipping");
+            return;
+        }
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"!?").unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(b"Hello World!\n!?\x03\x02\x01Az\x18\x08\x09".to_vec(), output.stdout);
+    }
+}
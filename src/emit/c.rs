@@ -0,0 +1,214 @@
+use std::fmt::Write;
+use brain_fuck_parser::{DEBUG_WINDOW, Node};
+
+use crate::{Config, Error};
+use super::check_supported;
+
+/// Translates the optimized tree into a standalone C program, the same way `bf!`
+/// translates it into Rust: the tape is a static array, loops become `while`s and
+/// optimized nodes become straight-line statements. Tape bounds are not checked.
+/// `Node::Debug` dumps the tape to stderr like the interpreters. Procedures become functions
+/// taking and returning the cell pointer, called through a table of function pointers;
+/// calling an undefined one exits with status 1. The storage register is a static variable,
+/// and `Node::End` exits.
+pub fn emit_c(program: &Node, config: &Config) -> Result<String, Error> {
+    check_supported(program, "C")?;
+    let mut procedures = Vec::new();
+    let mut main = String::new();
+    program.write_c(&mut main, 1, &mut procedures);
+
+    let mut out = String::new();
+    writeln!(out, "#include <stdio.h>").unwrap();
+    writeln!(out, "#include <stddef.h>").unwrap();
+    if program.uses_procedures() || program.ends() {
+        writeln!(out, "#include <stdlib.h>").unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "static unsigned char tape[{}];", config.tape_size).unwrap();
+    if program.uses_storage() {
+        writeln!(out, "static unsigned char storage;").unwrap();
+    }
+    if program.uses_procedures() {
+        writeln!(out, "static size_t (*procedures[256])(size_t);").unwrap();
+        writeln!(out).unwrap();
+        for index in 0..procedures.len() {
+            writeln!(out, "static size_t procedure{index}(size_t tape_pos);").unwrap();
+        }
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "int main(void) {{").unwrap();
+    writeln!(out, "    size_t tape_pos = 0;").unwrap();
+    out.push_str(&main);
+    writeln!(out, "    return 0;").unwrap();
+    writeln!(out, "}}").unwrap();
+    for procedure in procedures {
+        writeln!(out).unwrap();
+        out.push_str(&procedure);
+    }
+    Ok(out)
+}
+
+trait ToC {
+    /// Writes the statements at `depth` levels of indentation, adding the functions of the
+    /// procedures defined to `procedures`.
+    fn write_c(&self, out: &mut String, depth: usize, procedures: &mut Vec<String>);
+}
+
+impl ToC for Node {
+    fn write_c(&self, out: &mut String, depth: usize, procedures: &mut Vec<String>) {
+        let indent = "    ".repeat(depth);
+        match self {
+            Node::Root(nodes) => nodes.iter().for_each(|node| node.write_c(out, depth, procedures)),
+            Node::Inc(amount) => writeln!(out, "{indent}tape[tape_pos] += {amount};").unwrap(),
+            Node::Dec(amount) => writeln!(out, "{indent}tape[tape_pos] -= {amount};").unwrap(),
+            Node::IncTapePos(offset) => writeln!(out, "{indent}tape_pos += {offset};").unwrap(),
+            Node::DecTapePos(offset) => writeln!(out, "{indent}tape_pos -= {offset};").unwrap(),
+            Node::IncTapePosUntilEmpty => writeln!(out, "{indent}while (tape[tape_pos]) tape_pos++;").unwrap(),
+            Node::DecTapePosUntilEmpty => writeln!(out, "{indent}while (tape[tape_pos]) tape_pos--;").unwrap(),
+            Node::PutChar => writeln!(out, "{indent}putchar(tape[tape_pos]);").unwrap(),
+            Node::GetChar => writeln!(out, "{indent}tape[tape_pos] = (unsigned char)getchar();").unwrap(),
+            Node::Clear => writeln!(out, "{indent}tape[tape_pos] = 0;").unwrap(),
+            Node::AddToTheRightAndClear(offset) => {
+                writeln!(out, "{indent}tape[tape_pos + {offset}] += tape[tape_pos];").unwrap();
+                writeln!(out, "{indent}tape[tape_pos] = 0;").unwrap();
+            }
+            Node::DecFromTheRightAndClear(offset) => {
+                writeln!(out, "{indent}tape[tape_pos + {offset}] -= tape[tape_pos];").unwrap();
+                writeln!(out, "{indent}tape[tape_pos] = 0;").unwrap();
+            }
+            Node::AddToTheLeftAndClear(offset) => {
+                writeln!(out, "{indent}if (tape[tape_pos]) {{").unwrap();
+                writeln!(out, "{indent}    tape[tape_pos - {offset}] += tape[tape_pos];").unwrap();
+                writeln!(out, "{indent}    tape[tape_pos] = 0;").unwrap();
+                writeln!(out, "{indent}}}").unwrap();
+            }
+            Node::DecFromTheLeftAndClear(offset) => {
+                writeln!(out, "{indent}if (tape[tape_pos]) {{").unwrap();
+                writeln!(out, "{indent}    tape[tape_pos - {offset}] -= tape[tape_pos];").unwrap();
+                writeln!(out, "{indent}    tape[tape_pos] = 0;").unwrap();
+                writeln!(out, "{indent}}}").unwrap();
+            }
+            Node::Debug => {
+                let window = DEBUG_WINDOW;
+                writeln!(out, "{indent}{{").unwrap();
+                writeln!(out, "{indent}    size_t start = tape_pos > {window} ? tape_pos - {window} : 0;").unwrap();
+                writeln!(out, "{indent}    size_t end = tape_pos + {} < sizeof tape ? tape_pos + {} : sizeof tape;", window + 1, window + 1).unwrap();
+                writeln!(out, "{indent}    fflush(stdout);").unwrap();
+                writeln!(out, "{indent}    fprintf(stderr, \"tape_pos %zu, cells %zu..%zu: [\", tape_pos, start, end);").unwrap();
+                writeln!(out, "{indent}    for (size_t i = start; i < end; i++) fprintf(stderr, i == start ? \"%d\" : \", %d\", tape[i]);").unwrap();
+                writeln!(out, "{indent}    fprintf(stderr, \"]\\n\");").unwrap();
+                writeln!(out, "{indent}}}").unwrap();
+            }
+            Node::Procedure(nodes) => {
+                let index = procedures.len();
+                procedures.push(String::new());
+                let mut function = String::new();
+                writeln!(function, "static size_t procedure{index}(size_t tape_pos) {{").unwrap();
+                nodes.iter().for_each(|node| node.write_c(&mut function, 1, procedures));
+                writeln!(function, "    return tape_pos;").unwrap();
+                writeln!(function, "}}").unwrap();
+                procedures[index] = function;
+                writeln!(out, "{indent}procedures[tape[tape_pos]] = procedure{index};").unwrap();
+            }
+            Node::Call => {
+                writeln!(out, "{indent}if (!procedures[tape[tape_pos]]) {{").unwrap();
+                writeln!(out, "{indent}    fflush(stdout);").unwrap();
+                writeln!(out, "{indent}    fprintf(stderr, \"called undefined procedure %d\\n\", tape[tape_pos]);").unwrap();
+                writeln!(out, "{indent}    exit(1);").unwrap();
+                writeln!(out, "{indent}}}").unwrap();
+                writeln!(out, "{indent}tape_pos = procedures[tape[tape_pos]](tape_pos);").unwrap();
+            }
+            Node::Loop(nodes) => {
+                writeln!(out, "{indent}while (tape[tape_pos]) {{").unwrap();
+                nodes.iter().for_each(|node| node.write_c(out, depth + 1, procedures));
+                writeln!(out, "{indent}}}").unwrap();
+            }
+            Node::Fork => unreachable!(),
+            Node::End => writeln!(out, "{indent}exit(0);").unwrap(),
+            Node::Store => writeln!(out, "{indent}storage = tape[tape_pos];").unwrap(),
+            Node::Retrieve => writeln!(out, "{indent}tape[tape_pos] = storage;").unwrap(),
+            Node::ShiftLeft => writeln!(out, "{indent}tape[tape_pos] <<= 1;").unwrap(),
+            Node::ShiftRight => writeln!(out, "{indent}tape[tape_pos] >>= 1;").unwrap(),
+            Node::Not => writeln!(out, "{indent}tape[tape_pos] = ~tape[tape_pos];").unwrap(),
+            Node::Xor => writeln!(out, "{indent}tape[tape_pos] ^= storage;").unwrap(),
+            Node::And => writeln!(out, "{indent}tape[tape_pos] &= storage;").unwrap(),
+            Node::Or => writeln!(out, "{indent}tape[tape_pos] |= storage;").unwrap(),
+            Node::Comment { .. } => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Stdio};
+    use std::io::Write;
+    use brain_fuck_parser::{Extensions, parse_bf, try_parse_bf_with};
+    use crate::{Config, Machine};
+    use crate::machine::dump_tape;
+    use super::emit_c;
+
+    #[test]
+    fn emits_loops_and_optimized_nodes() {
+        let config = Config { tape_size: 16, ..Config::default() };
+        assert_eq!(
+            emit_c(&parse_bf("++[->+<]>[<]>.,[-<->]"), &config).unwrap(),
+            "\
+#include <stdio.h>
+#include <stddef.h>
+
+static unsigned char tape[16];
+
+int main(void) {
+    size_t tape_pos = 0;
+    tape[tape_pos] += 2;
+    tape[tape_pos + 1] += tape[tape_pos];
+    tape[tape_pos] = 0;
+    tape_pos += 1;
+    while (tape[tape_pos]) tape_pos--;
+    tape_pos += 1;
+    putchar(tape[tape_pos]);
+    tape[tape_pos] = (unsigned char)getchar();
+    if (tape[tape_pos]) {
+        tape[tape_pos - 1] -= tape[tape_pos];
+        tape[tape_pos] = 0;
+    }
+    return 0;
+}
+"
+        );
+    }
+
+    /// Builds the emitted source with the system C compiler, if there is one.
+    #[test]
+    fn compiled_program_runs() {
+        let dir = std::env::temp_dir().join(format!("bf-emit-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("hello.c");
+        let binary = dir.join("hello");
+        let hello = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        let code = format!("{hello}#,+[-.,+]>>>(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:++++{{$>+++^~}}.<-!{{|.&.+(.@):+.");
+        let extensions = Extensions { debug: true, procedures: true, extended: true, ..Extensions::default() };
+        let program = try_parse_bf_with(&code, extensions).unwrap();
+        std::fs::write(&source, emit_c(&program, &Config::default()).unwrap()).unwrap();
+        let status = Command::new("cc").arg("-O2").arg("-o").arg(&binary).arg(&source).status();
+        if !matches!(status, Ok(status) if status.success()) {
+            eprintln!("no working C compiler, skipping");
+            return;
+        }
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(b"!").unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(b"Hello World!\n!\x03\x02\x01Az\x18\x08\x09".to_vec(), output.stdout);
+
+        let mut machine = Machine::parse(hello, Config::default(), &b""[..], Vec::new()).unwrap();
+        machine.run().unwrap();
+        let dump = dump_tape(machine.tape(), machine.tape_pos());
+        assert_eq!(format!("{dump}\n"), String::from_utf8(output.stderr).unwrap());
+    }
+}
@@ -0,0 +1,167 @@
+@tape = internal global [16 x i8] zeroinitializer
+
+declare i32 @getchar()
+declare i32 @putchar(i32)
+
+define i32 @main() {
+entry:
+  %pos = alloca i64
+  store i64 0, ptr %pos
+  %v0 = load i64, ptr %pos
+  %v1 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v0
+  %v2 = load i8, ptr %v1
+  %v3 = add i8 %v2, 2
+  store i8 %v3, ptr %v1
+  %v4 = load i64, ptr %pos
+  %v5 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v4
+  %v6 = load i8, ptr %v5
+  %v7 = load i64, ptr %pos
+  %v8 = add i64 %v7, 1
+  %v9 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v8
+  %v10 = load i8, ptr %v9
+  %v11 = add i8 %v10, %v6
+  store i8 %v11, ptr %v9
+  store i8 0, ptr %v5
+  %v12 = load i64, ptr %pos
+  %v13 = add i64 %v12, 1
+  store i64 %v13, ptr %pos
+  br label %scan0.cond
+scan0.cond:
+  %v14 = load i64, ptr %pos
+  %v15 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v14
+  %v16 = load i8, ptr %v15
+  %v17 = icmp ne i8 %v16, 0
+  br i1 %v17, label %scan0.body, label %scan0.end
+scan0.body:
+  %v18 = load i64, ptr %pos
+  %v19 = sub i64 %v18, 1
+  store i64 %v19, ptr %pos
+  br label %scan0.cond
+scan0.end:
+  %v20 = load i64, ptr %pos
+  %v21 = add i64 %v20, 1
+  store i64 %v21, ptr %pos
+  br label %scan1.cond
+scan1.cond:
+  %v22 = load i64, ptr %pos
+  %v23 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v22
+  %v24 = load i8, ptr %v23
+  %v25 = icmp ne i8 %v24, 0
+  br i1 %v25, label %scan1.body, label %scan1.end
+scan1.body:
+  %v26 = load i64, ptr %pos
+  %v27 = add i64 %v26, 1
+  store i64 %v27, ptr %pos
+  br label %scan1.cond
+scan1.end:
+  %v28 = load i64, ptr %pos
+  %v29 = sub i64 %v28, 1
+  store i64 %v29, ptr %pos
+  %v30 = load i64, ptr %pos
+  %v31 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v30
+  %v32 = load i8, ptr %v31
+  %v33 = zext i8 %v32 to i32
+  %v34 = call i32 @putchar(i32 %v33)
+  %v35 = load i64, ptr %pos
+  %v36 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v35
+  %v37 = call i32 @getchar()
+  %v38 = trunc i32 %v37 to i8
+  store i8 %v38, ptr %v36
+  %v39 = load i64, ptr %pos
+  %v40 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v39
+  %v41 = load i8, ptr %v40
+  %v42 = icmp ne i8 %v41, 0
+  br i1 %v42, label %if2.then, label %if2.end
+if2.then:
+  %v43 = load i64, ptr %pos
+  %v44 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v43
+  %v45 = load i8, ptr %v44
+  %v46 = load i64, ptr %pos
+  %v47 = sub i64 %v46, 1
+  %v48 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v47
+  %v49 = load i8, ptr %v48
+  %v50 = sub i8 %v49, %v45
+  store i8 %v50, ptr %v48
+  store i8 0, ptr %v44
+  br label %if2.end
+if2.end:
+  %v51 = load i64, ptr %pos
+  %v52 = sub i64 %v51, 1
+  store i64 %v52, ptr %pos
+  %v53 = load i64, ptr %pos
+  %v54 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v53
+  %v55 = load i8, ptr %v54
+  %v56 = icmp ne i8 %v55, 0
+  br i1 %v56, label %if3.then, label %if3.end
+if3.then:
+  %v57 = load i64, ptr %pos
+  %v58 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v57
+  %v59 = load i8, ptr %v58
+  %v60 = load i64, ptr %pos
+  %v61 = sub i64 %v60, 1
+  %v62 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v61
+  %v63 = load i8, ptr %v62
+  %v64 = add i8 %v63, %v59
+  store i8 %v64, ptr %v62
+  store i8 0, ptr %v58
+  br label %if3.end
+if3.end:
+  %v65 = load i64, ptr %pos
+  %v66 = add i64 %v65, 3
+  store i64 %v66, ptr %pos
+  %v67 = load i64, ptr %pos
+  %v68 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v67
+  store i8 0, ptr %v68
+  %v69 = load i64, ptr %pos
+  %v70 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v69
+  %v71 = load i8, ptr %v70
+  %v72 = add i8 %v71, -3
+  store i8 %v72, ptr %v70
+  %v73 = load i64, ptr %pos
+  %v74 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v73
+  %v75 = load i8, ptr %v74
+  %v76 = load i64, ptr %pos
+  %v77 = add i64 %v76, 2
+  %v78 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v77
+  %v79 = load i8, ptr %v78
+  %v80 = add i8 %v79, %v75
+  store i8 %v80, ptr %v78
+  store i8 0, ptr %v74
+  %v81 = load i64, ptr %pos
+  %v82 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v81
+  %v83 = load i8, ptr %v82
+  %v84 = add i8 %v83, 1
+  store i8 %v84, ptr %v82
+  br label %loop4.cond
+loop4.cond:
+  %v85 = load i64, ptr %pos
+  %v86 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v85
+  %v87 = load i8, ptr %v86
+  %v88 = icmp ne i8 %v87, 0
+  br i1 %v88, label %loop4.body, label %loop4.end
+loop4.body:
+  %v89 = load i64, ptr %pos
+  %v90 = add i64 %v89, 1
+  store i64 %v90, ptr %pos
+  %v91 = load i64, ptr %pos
+  %v92 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v91
+  %v93 = load i8, ptr %v92
+  %v94 = add i8 %v93, 1
+  store i8 %v94, ptr %v92
+  %v95 = load i64, ptr %pos
+  %v96 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v95
+  %v97 = load i8, ptr %v96
+  %v98 = zext i8 %v97 to i32
+  %v99 = call i32 @putchar(i32 %v98)
+  %v100 = load i64, ptr %pos
+  %v101 = sub i64 %v100, 1
+  store i64 %v101, ptr %pos
+  %v102 = load i64, ptr %pos
+  %v103 = getelementptr inbounds [16 x i8], ptr @tape, i64 0, i64 %v102
+  %v104 = load i8, ptr %v103
+  %v105 = add i8 %v104, -1
+  store i8 %v105, ptr %v103
+  br label %loop4.cond
+loop4.end:
+  ret i32 0
+}
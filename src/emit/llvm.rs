@@ -0,0 +1,333 @@
+use std::fmt::Write;
+use brain_fuck_parser::Node;
+
+use crate::{Config, Error};
+use super::check_supported;
+
+/// Translates the optimized tree into a self-contained textual LLVM IR module with a `main`
+/// function, calling `getchar` and `putchar` from libc.
+///
+/// The tape is a zero-initialized global array and the cell pointer an `alloca`, loaded and
+/// stored around every operation so no phi nodes are needed; `mem2reg` promotes it to a
+/// register. Loops become a condition, a body and an exit block. Pointers are opaque (`ptr`),
+/// which LLVM 14 only reads with `-opaque-pointers`. Tape bounds are not checked, and
+/// `Node::Debug` is compiled to nothing. Procedures become functions taking and returning the
+/// cell pointer, called through a global table; calling an undefined one exits with status 1.
+/// The storage register is a global too, and `Node::End` calls `exit`.
+pub fn emit_llvm(program: &Node, config: &Config) -> Result<String, Error> {
+    check_supported(program, "LLVM")?;
+    let mut function = Function::new(config.tape_size, Vec::new());
+    program.write_llvm(&mut function);
+
+    let mut out = String::new();
+    writeln!(out, "@tape = internal global [{} x i8] zeroinitializer", config.tape_size).unwrap();
+    if program.uses_procedures() {
+        writeln!(out, "@procedures = internal global [256 x ptr] zeroinitializer").unwrap();
+    }
+    if program.uses_storage() {
+        writeln!(out, "@storage = internal global i8 0").unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "declare i32 @getchar()").unwrap();
+    writeln!(out, "declare i32 @putchar(i32)").unwrap();
+    if program.uses_procedures() || program.ends() {
+        writeln!(out, "declare void @exit(i32)").unwrap();
+    }
+    writeln!(out).unwrap();
+    writeln!(out, "define i32 @main() {{").unwrap();
+    writeln!(out, "entry:").unwrap();
+    writeln!(out, "  %pos = alloca i64").unwrap();
+    writeln!(out, "  store i64 0, ptr %pos").unwrap();
+    out.push_str(&function.out);
+    writeln!(out, "  ret i32 0").unwrap();
+    writeln!(out, "}}").unwrap();
+    for procedure in function.procedures {
+        writeln!(out).unwrap();
+        out.push_str(&procedure);
+    }
+    Ok(out)
+}
+
+/// Body of `main` or of a procedure being generated, with counters for unique value and block
+/// names, and the functions of the procedures defined so far.
+struct Function {
+    out: String,
+    tape_size: usize,
+    values: usize,
+    blocks: usize,
+    procedures: Vec<String>
+}
+
+impl Function {
+    fn new(tape_size: usize, procedures: Vec<String>) -> Self {
+        Self { out: String::new(), tape_size, values: 0, blocks: 0, procedures }
+    }
+
+    /// Appends an instruction producing a value, returning its name.
+    fn value(&mut self, instruction: &str) -> String {
+        let name = format!("%v{}", self.values);
+        self.values += 1;
+        writeln!(self.out, "  {name} = {instruction}").unwrap();
+        name
+    }
+
+    fn instruction(&mut self, instruction: &str) {
+        writeln!(self.out, "  {instruction}").unwrap();
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.out, "{label}:").unwrap();
+    }
+
+    fn next_block(&mut self) -> usize {
+        self.blocks += 1;
+        self.blocks - 1
+    }
+
+    /// Loads the cell pointer, offset by `offset` cells.
+    fn position(&mut self, offset: isize) -> String {
+        let pos = self.value("load i64, ptr %pos");
+        match offset {
+            0 => pos,
+            1.. => self.value(&format!("add i64 {pos}, {offset}")),
+            _ => self.value(&format!("sub i64 {pos}, {}", -offset))
+        }
+    }
+
+    /// Address of the cell `offset` cells away from the current one.
+    fn cell(&mut self, offset: isize) -> String {
+        let pos = self.position(offset);
+        self.value(&format!("getelementptr inbounds [{} x i8], ptr @tape, i64 0, i64 {pos}", self.tape_size))
+    }
+
+    fn move_pos(&mut self, offset: isize) {
+        let pos = self.position(offset);
+        self.instruction(&format!("store i64 {pos}, ptr %pos"));
+    }
+
+    /// Adds `amount`, interpreted modulo 256, to the current cell.
+    fn add(&mut self, amount: isize) {
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let sum = self.value(&format!("add i8 {value}, {}", amount as i8));
+        self.instruction(&format!("store i8 {sum}, ptr {cell}"));
+    }
+
+    /// Adds or subtracts the current cell to or from the one `offset` cells away,
+    /// then clears the current cell.
+    fn transfer(&mut self, offset: isize, op: &str) {
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let target = self.cell(offset);
+        let previous = self.value(&format!("load i8, ptr {target}"));
+        let result = self.value(&format!("{op} i8 {previous}, {value}"));
+        self.instruction(&format!("store i8 {result}, ptr {target}"));
+        self.instruction(&format!("store i8 0, ptr {cell}"));
+    }
+
+    /// Emits `while (tape[pos] != 0) { body }`, with blocks named after `kind`.
+    fn loop_while_nonzero(&mut self, kind: &str, body: impl FnOnce(&mut Self)) {
+        let block = self.next_block();
+        self.instruction(&format!("br label %{kind}{block}.cond"));
+        self.label(&format!("{kind}{block}.cond"));
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let nonzero = self.value(&format!("icmp ne i8 {value}, 0"));
+        self.instruction(&format!("br i1 {nonzero}, label %{kind}{block}.body, label %{kind}{block}.end"));
+        self.label(&format!("{kind}{block}.body"));
+        body(self);
+        self.instruction(&format!("br label %{kind}{block}.cond"));
+        self.label(&format!("{kind}{block}.end"));
+    }
+
+    /// Address of the entry of `@procedures` numbered by the current cell.
+    fn procedure_slot(&mut self) -> String {
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let number = self.value(&format!("zext i8 {value} to i64"));
+        self.value(&format!("getelementptr inbounds [256 x ptr], ptr @procedures, i64 0, i64 {number}"))
+    }
+
+    /// Emits `@procedureN`, a function running `body` from the cell pointer it takes and
+    /// returning where it leaves it, and stores it to the table.
+    fn define_procedure(&mut self, body: &[Node]) {
+        let index = self.procedures.len();
+        self.procedures.push(String::new());
+        let mut procedure = Function::new(self.tape_size, std::mem::take(&mut self.procedures));
+        body.iter().for_each(|node| node.write_llvm(&mut procedure));
+        let pos = procedure.value("load i64, ptr %pos");
+        procedure.instruction(&format!("ret i64 {pos}"));
+        self.procedures = procedure.procedures;
+        self.procedures[index] = format!(
+            "define internal i64 @procedure{index}(i64 %start) {{\nentry:\n  %pos = alloca i64\n  store i64 %start, ptr %pos\n{}}}\n",
+            procedure.out
+        );
+        let slot = self.procedure_slot();
+        self.instruction(&format!("store ptr @procedure{index}, ptr {slot}"));
+    }
+
+    /// Calls the procedure numbered by the current cell, exiting if there is none.
+    fn call_procedure(&mut self) {
+        let block = self.next_block();
+        let slot = self.procedure_slot();
+        let procedure = self.value(&format!("load ptr, ptr {slot}"));
+        let undefined = self.value(&format!("icmp eq ptr {procedure}, null"));
+        self.instruction(&format!("br i1 {undefined}, label %call{block}.undefined, label %call{block}.defined"));
+        self.label(&format!("call{block}.undefined"));
+        self.instruction("call void @exit(i32 1)");
+        self.instruction("unreachable");
+        self.label(&format!("call{block}.defined"));
+        let pos = self.value("load i64, ptr %pos");
+        let result = self.value(&format!("call i64 {procedure}(i64 {pos})"));
+        self.instruction(&format!("store i64 {result}, ptr %pos"));
+    }
+
+    /// Replaces the current cell with the value of `instruction`, given the cell's value.
+    fn update(&mut self, instruction: impl FnOnce(&str) -> String) {
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let result = self.value(&instruction(&value));
+        self.instruction(&format!("store i8 {result}, ptr {cell}"));
+    }
+
+    /// Combines the current cell with the storage register by `op`.
+    fn combine_with_storage(&mut self, op: &str) {
+        let storage = self.value("load i8, ptr @storage");
+        self.update(|value| format!("{op} i8 {value}, {storage}"));
+    }
+
+    /// Exits, flushing the output, in a block of its own.
+    fn end_program(&mut self) {
+        let block = self.next_block();
+        self.instruction("call void @exit(i32 0)");
+        self.instruction("unreachable");
+        self.label(&format!("end{block}.after"));
+    }
+
+    /// Emits `if (tape[pos] != 0) { body }`.
+    fn if_nonzero(&mut self, body: impl FnOnce(&mut Self)) {
+        let block = self.next_block();
+        let cell = self.cell(0);
+        let value = self.value(&format!("load i8, ptr {cell}"));
+        let nonzero = self.value(&format!("icmp ne i8 {value}, 0"));
+        self.instruction(&format!("br i1 {nonzero}, label %if{block}.then, label %if{block}.end"));
+        self.label(&format!("if{block}.then"));
+        body(self);
+        self.instruction(&format!("br label %if{block}.end"));
+        self.label(&format!("if{block}.end"));
+    }
+}
+
+trait ToLlvm {
+    fn write_llvm(&self, function: &mut Function);
+}
+
+impl ToLlvm for Node {
+    fn write_llvm(&self, function: &mut Function) {
+        match self {
+            Node::Root(nodes) => nodes.iter().for_each(|node| node.write_llvm(function)),
+            Node::Inc(amount) => function.add(*amount as isize),
+            Node::Dec(amount) => function.add(-(*amount as isize)),
+            Node::IncTapePos(offset) => function.move_pos(*offset as isize),
+            Node::DecTapePos(offset) => function.move_pos(-(*offset as isize)),
+            Node::IncTapePosUntilEmpty => function.loop_while_nonzero("scan", |f| f.move_pos(1)),
+            Node::DecTapePosUntilEmpty => function.loop_while_nonzero("scan", |f| f.move_pos(-1)),
+            Node::PutChar => {
+                let cell = function.cell(0);
+                let value = function.value(&format!("load i8, ptr {cell}"));
+                let char = function.value(&format!("zext i8 {value} to i32"));
+                function.value(&format!("call i32 @putchar(i32 {char})"));
+            }
+            Node::GetChar => {
+                let cell = function.cell(0);
+                let char = function.value("call i32 @getchar()");
+                let value = function.value(&format!("trunc i32 {char} to i8"));
+                function.instruction(&format!("store i8 {value}, ptr {cell}"));
+            }
+            Node::Clear => {
+                let cell = function.cell(0);
+                function.instruction(&format!("store i8 0, ptr {cell}"));
+            }
+            Node::AddToTheRightAndClear(offset) => function.transfer(*offset as isize, "add"),
+            Node::DecFromTheRightAndClear(offset) => function.transfer(*offset as isize, "sub"),
+            Node::AddToTheLeftAndClear(offset) => {
+                function.if_nonzero(|f| f.transfer(-(*offset as isize), "add"))
+            }
+            Node::DecFromTheLeftAndClear(offset) => {
+                function.if_nonzero(|f| f.transfer(-(*offset as isize), "sub"))
+            }
+            Node::Debug => {}
+            Node::Procedure(nodes) => function.define_procedure(nodes),
+            Node::Call => function.call_procedure(),
+            Node::Loop(nodes) => function.loop_while_nonzero("loop", |f| {
+                nodes.iter().for_each(|node| node.write_llvm(f))
+            }),
+            Node::Fork => unreachable!(),
+            Node::End => function.end_program(),
+            Node::Store => {
+                let cell = function.cell(0);
+                let value = function.value(&format!("load i8, ptr {cell}"));
+                function.instruction(&format!("store i8 {value}, ptr @storage"));
+            }
+            Node::Retrieve => {
+                let cell = function.cell(0);
+                let value = function.value("load i8, ptr @storage");
+                function.instruction(&format!("store i8 {value}, ptr {cell}"));
+            }
+            Node::ShiftLeft => function.update(|value| format!("shl i8 {value}, 1")),
+            Node::ShiftRight => function.update(|value| format!("lshr i8 {value}, 1")),
+            Node::Not => function.update(|value| format!("xor i8 {value}, -1")),
+            Node::Xor => function.combine_with_storage("xor"),
+            Node::And => function.combine_with_storage("and"),
+            Node::Or => function.combine_with_storage("or"),
+            Node::Comment { .. } => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+    use brain_fuck_parser::{Extensions, parse_bf, try_parse_bf_with};
+    use crate::Config;
+    use super::emit_llvm;
+
+    /// Covers every node; regenerate with `bf compile --emit llvm` on the program
+    /// and a tape of 16 cells when the output changes on purpose.
+    const GOLDEN_PROGRAM: &str = "++[->+<]>[<]>[>]<.,[-<->]<[-<+>]>>>[-]---[->>+<<]+[>+.<-]";
+
+    #[test]
+    fn output_matches_golden_file() {
+        let config = Config { tape_size: 16, ..Config::default() };
+        assert_eq!(include_str!("golden/llvm.ll"), emit_llvm(&parse_bf(GOLDEN_PROGRAM), &config).unwrap());
+    }
+
+    /// Runs the module with `lli`, if there is one.
+    #[test]
+    fn interpreted_module_runs() {
+        let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,+[-.,+]\
+            >>>(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:++++{$>+++^~}.<-!{|.&.+(.@):+.";
+        let program = try_parse_bf_with(code, Extensions { procedures: true, extended: true, ..Extensions::default() }).unwrap();
+        let module = emit_llvm(&program, &Config::default()).unwrap();
+        // LLVM before 15 needs the flag for opaque pointers, later versions reject it.
+        for flags in [&[][..], &["-opaque-pointers"][..]] {
+            let Ok(mut child) = Command::new("lli")
+                .args(flags)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn() else {
+                eprintln!("no lli, skipping");
+                return;
+            };
+            child.stdin.take().unwrap().write_all(module.as_bytes()).unwrap();
+            let output = child.wait_with_output().unwrap();
+            if output.status.success() {
+                assert_eq!(b"Hello World!\n\x03\x02\x01Az\x18\x08\x09".to_vec(), output.stdout);
+                return;
+            }
+        }
+        panic!("lli failed to run the module");
+    }
+}
@@ -0,0 +1,74 @@
+//! Ahead-of-time compilers from the optimized `Node` tree to other languages.
+//!
+//! Programs forking with `Node::Fork` are rejected with `Error::Unsupported`.
+
+mod asm;
+mod c;
+mod llvm;
+mod rust;
+mod wasm;
+
+use std::str::FromStr;
+use brain_fuck_parser::Node;
+
+use crate::{Config, Error};
+
+pub use asm::emit_asm;
+pub use c::emit_c;
+pub use llvm::emit_llvm;
+pub use rust::{RustCrate, emit_rust};
+pub use wasm::{emit_wasm, emit_wat};
+
+/// Output format of `bf compile`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Emit {
+    /// x86-64 Linux assembly in GNU as syntax.
+    Asm,
+    C,
+    /// Textual LLVM IR.
+    Llvm,
+    /// A binary crate, see `emit_rust`.
+    Rust,
+    /// A WebAssembly module in the binary format.
+    Wasm,
+    /// A WebAssembly module in the text format.
+    Wat
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asm" => Ok(Emit::Asm),
+            "c" => Ok(Emit::C),
+            "llvm" => Ok(Emit::Llvm),
+            "rust" => Ok(Emit::Rust),
+            "wasm" => Ok(Emit::Wasm),
+            "wat" => Ok(Emit::Wat),
+            _ => Err(format!("unknown output format {s}, expected one of: asm, c, llvm, rust, wasm, wat"))
+        }
+    }
+}
+
+/// Compiles the program into the requested format. Only `main.rs` is returned for
+/// `Emit::Rust`, use `emit_rust` for the whole crate.
+pub fn emit(program: &Node, format: Emit, config: &Config) -> Result<Vec<u8>, Error> {
+    Ok(match format {
+        Emit::Asm => emit_asm(program, config)?.into_bytes(),
+        Emit::C => emit_c(program, config)?.into_bytes(),
+        Emit::Llvm => emit_llvm(program, config)?.into_bytes(),
+        Emit::Rust => emit_rust(program, config, "bf-program")?.main.into_bytes(),
+        Emit::Wasm => emit_wasm(program, config)?,
+        Emit::Wat => emit_wat(program, config)?.into_bytes()
+    })
+}
+
+/// Rejects the programs `backend` can't compile, those forking threads, which need the
+/// scheduler of `Machine`.
+fn check_supported(program: &Node, backend: &'static str) -> Result<(), Error> {
+    if program.forks() {
+        return Err(Error::Unsupported { feature: "`Y` threads", backend });
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use std::path::Path;
+use brain_fuck_parser::Node;
+use quote::quote;
+
+use crate::{Config, Error};
+use super::check_supported;
+
+/// Sources of a standalone binary crate.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RustCrate {
+    /// `Cargo.toml`.
+    pub manifest: String,
+    /// `src/main.rs`.
+    pub main: String
+}
+
+impl RustCrate {
+    /// Writes the crate into `dir`, creating it if needed.
+    pub fn write_to(&self, dir: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(dir.join("src"))?;
+        std::fs::write(dir.join("Cargo.toml"), &self.manifest)?;
+        std::fs::write(dir.join("src").join("main.rs"), &self.main)
+    }
+}
+
+/// Generates a binary crate named `name` running the program, with the same code
+/// `bf!` expands to. Characters other than ASCII alphanumerics, `-` and `_` in the name
+/// are replaced with `_`. The crate declares its own workspace so it builds wherever it is put.
+pub fn emit_rust(program: &Node, config: &Config, name: &str) -> Result<RustCrate, Error> {
+    check_supported(program, "Rust")?;
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    let manifest = format!("\
+[package]
+name = \"{name}\"
+version = \"0.1.0\"
+edition = \"2021\"
+
+[workspace]
+
+[dependencies]
+libc = \"0.2\"
+");
+
+    let options = brain_fuck_codegen::Options { tape_size: config.tape_size, ..Default::default() };
+    let body = brain_fuck_codegen::function_body(program, &options);
+    let file = quote!(
+        fn main() {
+            #body
+        }
+    );
+    let file = syn::parse2(file).expect("code generator produced invalid Rust");
+    Ok(RustCrate { manifest, main: prettyplease::unparse(&file) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use brain_fuck_parser::parse_bf;
+    use crate::{Config, Machine};
+    use super::emit_rust;
+
+    #[test]
+    fn emits_formatted_crate() {
+        let config = Config { tape_size: 16, ..Config::default() };
+        let emitted = emit_rust(&parse_bf("++[->+<]>[<]>.,"), &config, "hello world").unwrap();
+        assert!(emitted.manifest.starts_with("[package]\nname = \"hello_world\"\n"));
+        assert!(emitted.manifest.contains("libc = \"0.2\""));
+        assert_eq!(
+            emitted.main,
+            "\
+fn main() {
+    let mut tape: Vec<u8> = vec![0; 16usize];
+    let mut tape_pos = 0;
+    tape[tape_pos] = tape[tape_pos].wrapping_add(2);
+    tape[tape_pos + 1usize] = tape[tape_pos + 1usize].wrapping_add(tape[tape_pos]);
+    tape[tape_pos] = 0;
+    tape_pos += 1usize;
+    while tape[tape_pos] != 0 {
+        tape_pos -= 1;
+    }
+    tape_pos += 1usize;
+    std::io::Write::write_all(&mut std::io::stdout(), &[tape[tape_pos]]).unwrap();
+    tape[tape_pos] = unsafe { libc::getchar() } as u8;
+}
+"
+        );
+    }
+
+    /// Builds the emitted `main.rs` with `rustc` in debug and release and compares the
+    /// output with the interpreter's. Programs read no input, so libc isn't needed.
+    #[test]
+    fn compiled_program_wraps_like_the_interpreter() {
+        let programs = [
+            "-.+.>+++++++++++++++[-<+++++++++++++++++++>]<.",
+            "-->-<[->+<]>.>+<[->-<]>.",
+            ">->--<[-<+>]<.>>>[-<<<->>>]<<<.",
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++."
+        ];
+        let dir = std::env::temp_dir().join(format!("bf-emit-rust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        for (i, code) in programs.into_iter().enumerate() {
+            let mut machine = Machine::parse(code, Config::default(), &b""[..], Vec::new()).unwrap();
+            machine.run().unwrap();
+            let expected = machine.into_output();
+
+            let source = dir.join(format!("program{i}.rs"));
+            std::fs::write(&source, emit_rust(&parse_bf(code), &Config::default(), "wrapping").unwrap().main).unwrap();
+            for profile in [&["-C", "debug-assertions=on", "-C", "overflow-checks=on"][..], &["-O"][..]] {
+                let binary = dir.join(format!("program{i}{}", profile.len()));
+                let status = Command::new(&rustc)
+                    .args(["--edition", "2021", "-o"])
+                    .arg(&binary)
+                    .args(profile)
+                    .arg(&source)
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+                let output = Command::new(&binary).output().unwrap();
+                assert!(output.status.success(), "{code} {profile:?}");
+                assert_eq!(expected, output.stdout, "{code} {profile:?}");
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
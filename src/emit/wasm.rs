@@ -0,0 +1,340 @@
+use std::fmt::Write;
+use brain_fuck_parser::Node;
+
+use crate::{Config, Error};
+use super::check_supported;
+
+const PAGE_SIZE: usize = 0x10000;
+
+/// Translates the optimized tree into a WebAssembly text module.
+///
+/// The tape is the exported linear memory, starting at address 0, and the cell pointer a local
+/// of the exported `main` function. Input and output go through `getchar: [] -> [i32]` and
+/// `putchar: [i32] -> []` imported from `env`; `getchar` returning -1 stores 255, same as
+/// `EOF_VALUE`. Moving off the tape traps on the memory access, unless the tape size is not a
+/// multiple of the 64KiB page size, in which case the rest of the last page is usable too.
+/// `Node::Debug` is compiled to nothing. Procedures are functions taking and returning the cell
+/// pointer, stored in a table of 256 entries; calling an undefined one traps. The storage
+/// register is a global. `Node::End` returns from `main`, and from a procedure returns -1,
+/// which its callers pass on.
+pub fn emit_wat(program: &Node, config: &Config) -> Result<String, Error> {
+    check_supported(program, "WebAssembly")?;
+    let pages = config.tape_size.div_ceil(PAGE_SIZE).max(1);
+    let mut main = String::new();
+    let mut procedures = Vec::new();
+    let end: &[&str] = if program.ends() { &["return"] } else { &[] };
+    program.write_wat(&mut main, &mut 0, 2, &mut procedures, end);
+
+    let mut out = String::new();
+    writeln!(out, "(module").unwrap();
+    writeln!(out, "  (import \"env\" \"getchar\" (func $getchar (result i32)))").unwrap();
+    writeln!(out, "  (import \"env\" \"putchar\" (func $putchar (param i32)))").unwrap();
+    writeln!(out, "  (memory (export \"memory\") {pages})").unwrap();
+    if program.uses_storage() {
+        writeln!(out, "  (global $storage (mut i32) (i32.const 0))").unwrap();
+    }
+    if program.uses_procedures() {
+        let names: Vec<_> = (0..procedures.len()).map(|index| format!("$procedure{index}")).collect();
+        writeln!(out, "  (type $procedure (func (param i32) (result i32)))").unwrap();
+        writeln!(out, "  (table 256 funcref)").unwrap();
+        writeln!(out, "  (elem declare func {})", names.join(" ")).unwrap();
+    }
+    writeln!(out, "  (func (export \"main\")").unwrap();
+    writeln!(out, "    (local $p i32)").unwrap();
+    out.push_str(&main);
+    writeln!(out, "  )").unwrap();
+    for (index, procedure) in procedures.iter().enumerate() {
+        writeln!(out, "  (func $procedure{index} (type $procedure) (param $p i32) (result i32)").unwrap();
+        out.push_str(procedure);
+        writeln!(out, "    local.get $p").unwrap();
+        writeln!(out, "  )").unwrap();
+    }
+    writeln!(out, ")").unwrap();
+    Ok(out)
+}
+
+/// Assembles the module of `emit_wat` into the binary format.
+pub fn emit_wasm(program: &Node, config: &Config) -> Result<Vec<u8>, Error> {
+    Ok(wat::parse_str(emit_wat(program, config)?).expect("code generator produced invalid WebAssembly"))
+}
+
+/// Instructions of a procedure ending the program.
+const PROCEDURE_END: [&str; 2] = ["i32.const -1", "return"];
+
+trait ToWat {
+    /// Writes the instructions at `depth` levels of indentation, numbering loops from `labels`,
+    /// and the bodies of the procedures it defines to `procedures`. `end` are the instructions
+    /// ending the program from the function being written, none if it never ends early.
+    fn write_wat(&self, out: &mut String, labels: &mut usize, depth: usize, procedures: &mut Vec<String>, end: &[&str]);
+}
+
+/// Writes the instructions, one per line.
+fn instructions(out: &mut String, depth: usize, lines: &[&str]) {
+    let indent = "  ".repeat(depth);
+    for line in lines {
+        writeln!(out, "{indent}{line}").unwrap();
+    }
+}
+
+/// Moves the cell pointer by `delta` until it points to an empty cell.
+fn scan(out: &mut String, labels: &mut usize, depth: usize, delta: &str) {
+    let label = *labels;
+    *labels += 1;
+    instructions(out, depth, &[
+        &format!("(block $exit{label}"),
+        &format!("  (loop $scan{label}"),
+        "    local.get $p",
+        "    i32.load8_u",
+        "    i32.eqz",
+        &format!("    br_if $exit{label}"),
+        "    local.get $p",
+        "    i32.const 1",
+        &format!("    {delta}"),
+        "    local.set $p",
+        &format!("    br $scan{label}))")
+    ]);
+}
+
+/// Replaces the current cell with the result of `ops` on its value.
+fn update(out: &mut String, depth: usize, ops: &[&str]) {
+    instructions(out, depth, &["local.get $p", "local.get $p", "i32.load8_u"]);
+    instructions(out, depth, ops);
+    instructions(out, depth, &["i32.store8"]);
+}
+
+/// Adds (`op` = `i32.add`) or subtracts (`i32.sub`) the current cell to or from the cell
+/// `offset` cells away in the direction of `direction`, then clears the current cell.
+fn transfer(out: &mut String, depth: usize, direction: &str, offset: usize, op: &str) {
+    instructions(out, depth, &[
+        "local.get $p",
+        &format!("i32.const {offset}"),
+        direction,
+        "local.get $p",
+        &format!("i32.const {offset}"),
+        direction,
+        "i32.load8_u",
+        "local.get $p",
+        "i32.load8_u",
+        op,
+        "i32.store8",
+        "local.get $p",
+        "i32.const 0",
+        "i32.store8"
+    ]);
+}
+
+impl ToWat for Node {
+    fn write_wat(&self, out: &mut String, labels: &mut usize, depth: usize, procedures: &mut Vec<String>, end: &[&str]) {
+        match self {
+            Node::Root(nodes) => nodes.iter().for_each(|node| node.write_wat(out, labels, depth, procedures, end)),
+            Node::Inc(amount) | Node::Dec(amount) => instructions(out, depth, &[
+                "local.get $p",
+                "local.get $p",
+                "i32.load8_u",
+                &format!("i32.const {amount}"),
+                if matches!(self, Node::Inc(_)) { "i32.add" } else { "i32.sub" },
+                "i32.store8"
+            ]),
+            Node::IncTapePos(offset) | Node::DecTapePos(offset) => instructions(out, depth, &[
+                "local.get $p",
+                &format!("i32.const {offset}"),
+                if matches!(self, Node::IncTapePos(_)) { "i32.add" } else { "i32.sub" },
+                "local.set $p"
+            ]),
+            Node::IncTapePosUntilEmpty => scan(out, labels, depth, "i32.add"),
+            Node::DecTapePosUntilEmpty => scan(out, labels, depth, "i32.sub"),
+            Node::PutChar => instructions(out, depth, &[
+                "local.get $p",
+                "i32.load8_u",
+                "call $putchar"
+            ]),
+            Node::GetChar => instructions(out, depth, &[
+                "local.get $p",
+                "call $getchar",
+                "i32.store8"
+            ]),
+            Node::Clear => instructions(out, depth, &[
+                "local.get $p",
+                "i32.const 0",
+                "i32.store8"
+            ]),
+            Node::AddToTheRightAndClear(offset) => transfer(out, depth, "i32.add", *offset, "i32.add"),
+            Node::DecFromTheRightAndClear(offset) => transfer(out, depth, "i32.add", *offset, "i32.sub"),
+            Node::AddToTheLeftAndClear(offset) | Node::DecFromTheLeftAndClear(offset) => {
+                let op = if matches!(self, Node::AddToTheLeftAndClear(_)) { "i32.add" } else { "i32.sub" };
+                instructions(out, depth, &["local.get $p", "i32.load8_u", "(if (then"]);
+                transfer(out, depth + 1, "i32.sub", *offset, op);
+                instructions(out, depth, &["))"]);
+            }
+            Node::Debug => {}
+            Node::Procedure(nodes) => {
+                let index = procedures.len();
+                procedures.push(String::new());
+                let mut body = String::new();
+                let end: &[&str] = if end.is_empty() { &[] } else { &PROCEDURE_END };
+                nodes.iter().for_each(|node| node.write_wat(&mut body, labels, 2, procedures, end));
+                procedures[index] = body;
+                instructions(out, depth, &[
+                    "local.get $p",
+                    "i32.load8_u",
+                    &format!("ref.func $procedure{index}"),
+                    "table.set 0"
+                ]);
+            }
+            Node::Call => {
+                instructions(out, depth, &[
+                    "local.get $p",
+                    "local.get $p",
+                    "i32.load8_u",
+                    "call_indirect (type $procedure)",
+                    "local.set $p"
+                ]);
+                if !end.is_empty() {
+                    instructions(out, depth, &["local.get $p", "i32.const -1", "i32.eq", "(if (then"]);
+                    instructions(out, depth + 1, end);
+                    instructions(out, depth, &["))"]);
+                }
+            }
+            Node::Loop(nodes) => {
+                let label = *labels;
+                *labels += 1;
+                instructions(out, depth, &[
+                    &format!("(block $exit{label}"),
+                    &format!("  (loop $loop{label}"),
+                    "    local.get $p",
+                    "    i32.load8_u",
+                    "    i32.eqz",
+                    &format!("    br_if $exit{label}")
+                ]);
+                nodes.iter().for_each(|node| node.write_wat(out, labels, depth + 2, procedures, end));
+                instructions(out, depth, &[&format!("    br $loop{label}))")]);
+            }
+            Node::Fork => unreachable!(),
+            Node::End => instructions(out, depth, end),
+            Node::Store => instructions(out, depth, &["local.get $p", "i32.load8_u", "global.set $storage"]),
+            Node::Retrieve => instructions(out, depth, &["local.get $p", "global.get $storage", "i32.store8"]),
+            Node::ShiftLeft => update(out, depth, &["i32.const 1", "i32.shl"]),
+            Node::ShiftRight => update(out, depth, &["i32.const 1", "i32.shr_u"]),
+            Node::Not => update(out, depth, &["i32.const -1", "i32.xor"]),
+            Node::Xor => update(out, depth, &["global.get $storage", "i32.xor"]),
+            Node::And => update(out, depth, &["global.get $storage", "i32.and"]),
+            Node::Or => update(out, depth, &["global.get $storage", "i32.or"]),
+            Node::Comment { .. } => unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brain_fuck_parser::{Extensions, Node, parse_bf, try_parse_bf_with};
+    use wasmparser::{Parser, Payload, TypeRef, Validator};
+    use crate::{Config, Machine};
+    use super::{emit_wasm, emit_wat};
+
+    const PROGRAMS: [&str; 6] = [
+        "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+        ",+[-.,+]",
+        ">>++++[<++++[<++++>-]>-]<<.[-]++[>+++<-]>[<+>-]<.>+++++[->+>++<<]>>[-<<->>]<<.[.-]",
+        "++++[>++++[>++++<-]<-]>>[<<+>>-]<<[>+>+<<-]>>.<.<[-]>>>++[<<+>[<+>-]>-]<<<.>.",
+        "(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:,(.+):",
+        "++++{$>+++^~}.<-!{|.,}.&.+(.@):+."
+    ];
+
+    fn extensions() -> Extensions {
+        Extensions { procedures: true, extended: true, ..Extensions::default() }
+    }
+
+    fn parse(code: &str) -> Node {
+        try_parse_bf_with(code, extensions()).unwrap()
+    }
+
+    #[test]
+    fn emits_loops_and_optimized_nodes() {
+        let wat = emit_wat(&parse_bf("+[>]<[-<+>]>,[.-]"), &Config::default()).unwrap();
+        assert!(wat.starts_with("(module\n"));
+        assert!(wat.contains("  (memory (export \"memory\") 16)\n"));
+        assert!(wat.contains("    (block $exit0\n      (loop $scan0\n"));
+        assert!(wat.contains("    (if (then\n"));
+        assert!(wat.contains("    (block $exit1\n      (loop $loop1\n"));
+        assert!(wat.contains("call $getchar"));
+        assert!(wat.contains("call $putchar"));
+    }
+
+    #[test]
+    fn module_is_valid_and_has_the_expected_interface() {
+        let config = Config { tape_size: 100, ..Config::default() };
+        for code in PROGRAMS {
+            let wasm = emit_wasm(&parse(code), &config).unwrap();
+            Validator::new().validate_all(&wasm).unwrap();
+
+            let mut imports = Vec::new();
+            let mut exports = Vec::new();
+            let mut memory_pages = None;
+            for payload in Parser::new(0).parse_all(&wasm) {
+                match payload.unwrap() {
+                    Payload::ImportSection(section) => {
+                        for import in section.into_imports() {
+                            let import = import.unwrap();
+                            assert!(matches!(import.ty, TypeRef::Func(_)));
+                            imports.push(format!("{}.{}", import.module, import.name));
+                        }
+                    }
+                    Payload::ExportSection(section) => {
+                        for export in section {
+                            exports.push(export.unwrap().name.to_string());
+                        }
+                    }
+                    Payload::MemorySection(section) => {
+                        for memory in section {
+                            memory_pages = Some(memory.unwrap().initial);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            assert_eq!(vec!["env.getchar", "env.putchar"], imports);
+            assert_eq!(vec!["memory", "main"], exports);
+            assert_eq!(Some(1), memory_pages);
+        }
+    }
+
+    struct Io {
+        input: Vec<u8>,
+        output: Vec<u8>
+    }
+
+    fn run_wasm(code: &str, input: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+        use wasmi::{Caller, Engine, Linker, Module, Store};
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &emit_wasm(&parse(code), &Config::default()).unwrap()[..])?;
+        let mut store = Store::new(&engine, Io { input: input.iter().rev().copied().collect(), output: Vec::new() });
+        let mut linker = Linker::new(&engine);
+        linker.func_wrap("env", "getchar", |mut caller: Caller<'_, Io>| {
+            caller.data_mut().input.pop().map_or(-1, i32::from)
+        })?;
+        linker.func_wrap("env", "putchar", |mut caller: Caller<'_, Io>, c: i32| {
+            caller.data_mut().output.push(c as u8);
+        })?;
+        let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
+        instance.get_typed_func::<(), ()>(&store, "main")?.call(&mut store, ())?;
+        Ok(store.into_data().output)
+    }
+
+    #[test]
+    fn output_matches_switch_backend() {
+        for code in PROGRAMS {
+            let config = Config { extensions: extensions(), ..Config::default() };
+            let mut machine = Machine::parse(code, config, &b"wasm"[..], Vec::new()).unwrap();
+            machine.run().unwrap();
+            assert_eq!(machine.into_output(), run_wasm(code, b"wasm").unwrap());
+        }
+    }
+
+    #[test]
+    fn moving_below_the_tape_traps() {
+        assert!(run_wasm("<+", b"").is_err());
+        assert!(run_wasm("+[<+]", b"").is_err());
+        assert!(run_wasm("+:", b"").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use std::fmt::{Display, Formatter};
+use brain_fuck_parser::ParseError;
+
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError),
+    TapeUnderflow { instruction_pointer: usize },
+    TapeOverflow { instruction_pointer: usize },
+    /// `Node::Call` with the current cell numbering no procedure defined so far.
+    UndefinedProcedure { procedure: u8, instruction_pointer: usize },
+    /// A call nested more than `MAX_CALL_DEPTH` calls deep.
+    CallStackOverflow { instruction_pointer: usize },
+    /// A program the backend can't run or compile, e.g. one forking with `Node::Fork` outside
+    /// `Machine`.
+    Unsupported { feature: &'static str, backend: &'static str },
+    Io(std::io::Error)
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "parse error: {err}"),
+            Error::TapeUnderflow { instruction_pointer } => {
+                write!(f, "tape pointer moved below zero at ip {instruction_pointer}")
+            }
+            Error::TapeOverflow { instruction_pointer } => {
+                write!(f, "tape pointer moved past the end of the tape at ip {instruction_pointer}")
+            }
+            Error::UndefinedProcedure { procedure, instruction_pointer } => {
+                write!(f, "called undefined procedure {procedure} at ip {instruction_pointer}")
+            }
+            Error::CallStackOverflow { instruction_pointer } => {
+                write!(f, "procedure calls nested too deep at ip {instruction_pointer}")
+            }
+            Error::Unsupported { feature, backend } => write!(f, "the {backend} backend doesn't support {feature}"),
+            Error::Io(err) => write!(f, "i/o error: {err}")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Error::Parse(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use brain_fuck_parser::SimOperation;
+
+use crate::{Error, Machine, Status};
+use crate::machine::{Bits, IoLog, Scheduler, Thread};
+
+/// How a single step changed the loop stack or the call stack of the machine.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum IpStackChange {
+    Unchanged,
+    Pushed,
+    Popped(usize)
+}
+
+/// Everything needed to revert a single executed step.
+#[derive(Clone, PartialEq, Debug)]
+struct UndoRecord {
+    instruction_pointer: usize,
+    tape_pos: usize,
+    ip_stack: IpStackChange,
+    call_stack: IpStackChange,
+    /// Number and previous body of the procedure the step defined.
+    procedure: Option<(usize, Option<usize>)>,
+    /// Running thread and scheduler before a step of a forking program, restored as a whole
+    /// as the step may have switched threads.
+    threads: Option<Box<(Thread, Scheduler)>>,
+    storage: u8,
+    io: IoPosition,
+    cells: [Option<(usize, u8)>; 2]
+}
+
+/// Reads and writes made by the program, and the bits of a Boolfuck byte in progress.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct IoPosition {
+    read: usize,
+    written: usize,
+    bits: Option<Bits>
+}
+
+impl IoPosition {
+    fn of<I: Read, O: Write>(ctx: &Machine<I, O>) -> Self {
+        let log = ctx.io_log.as_deref().expect("the machine has been stepped by History");
+        Self { read: log.read, written: log.written, bits: ctx.bits }
+    }
+
+    /// Rewinds the i/o of `ctx`, so the following reads and writes are replayed.
+    fn restore<I: Read, O: Write>(self, ctx: &mut Machine<I, O>) {
+        let log = ctx.io_log.as_deref_mut().expect("the machine has been stepped by History");
+        log.read = self.read;
+        log.written = self.written;
+        ctx.bits = self.bits;
+    }
+}
+
+impl IpStackChange {
+    /// Change from a stack of `len` return addresses with `top` on top.
+    fn between(len: usize, top: Option<usize>, stack: &[usize]) -> Self {
+        match (stack.len(), top) {
+            (new_len, _) if new_len > len => IpStackChange::Pushed,
+            (new_len, Some(top)) if new_len < len => IpStackChange::Popped(top),
+            _ => IpStackChange::Unchanged
+        }
+    }
+
+    fn revert(self, stack: &mut Vec<usize>) {
+        match self {
+            IpStackChange::Unchanged => {}
+            IpStackChange::Pushed => { stack.pop(); }
+            IpStackChange::Popped(ip) => stack.push(ip)
+        }
+    }
+}
+
+/// Full copy of the machine state taken every `snapshot_interval` steps.
+/// Trailing zero cells of the tape are not stored.
+#[derive(Clone, PartialEq, Debug)]
+struct Snapshot {
+    step: u64,
+    tape: Vec<u8>,
+    tape_pos: usize,
+    instruction_pointer: usize,
+    ip_stack: Vec<usize>,
+    call_stack: Vec<usize>,
+    procedures: [Option<usize>; 256],
+    storage: u8,
+    io: IoPosition,
+    scheduler: Scheduler
+}
+
+/// Memory bounded execution history, allowing to step a `Machine` backwards.
+///
+/// The last `record_capacity` steps are kept in a ring buffer of undo records, so they can
+/// be reverted one by one. Older states are only reachable through periodic snapshots:
+/// once the undo log is exhausted, stepping back lands on the closest older snapshot.
+///
+/// Steps taken again after stepping back read the same input as the first time, and don't
+/// write the output they already wrote.
+pub struct History {
+    records: VecDeque<UndoRecord>,
+    record_capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+    snapshot_interval: u64,
+    snapshot_capacity: usize,
+    step: u64
+}
+
+impl History {
+    pub fn new(record_capacity: usize, snapshot_interval: u64, snapshot_capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(record_capacity),
+            record_capacity,
+            snapshots: VecDeque::with_capacity(snapshot_capacity),
+            snapshot_interval: snapshot_interval.max(1),
+            snapshot_capacity,
+            step: 0
+        }
+    }
+
+    /// Number of steps executed since the start of the program.
+    pub fn current_step(&self) -> u64 {
+        self.step
+    }
+
+    /// Executes a single step of `ctx`, remembering how to revert it.
+    /// A step which fails is not committed, leaving the machine in the state before it.
+    pub fn step<I: Read, O: Write>(&mut self, ctx: &mut Machine<I, O>) -> Result<Status, Error> {
+        ctx.io_log.get_or_insert_with(Box::<IoLog>::default);
+        if self.step.is_multiple_of(self.snapshot_interval) && self.snapshot_capacity > 0 {
+            self.take_snapshot(ctx);
+        }
+
+        let op = ctx.current_operation();
+        let mut cells = [None; 2];
+        for (cell, pos) in cells.iter_mut().zip(ctx.cells_written_by(op)) {
+            *cell = pos.map(|pos| (pos, ctx.tape[pos]));
+        }
+        let instruction_pointer = ctx.instruction_pointer;
+        let tape_pos = ctx.tape_pos;
+        let stack_len = ctx.ip_stack.len();
+        let stack_top = ctx.ip_stack.last().copied();
+        let calls_len = ctx.call_stack.len();
+        let calls_top = ctx.call_stack.last().copied();
+        let procedure = match op {
+            SimOperation::DefineProcedure { .. } => {
+                let number = ctx.tape[ctx.tape_pos] as usize;
+                Some((number, ctx.procedures[number]))
+            }
+            _ => None
+        };
+        let threads = (ctx.threads() > 1 || op == SimOperation::Fork)
+            .then(|| Box::new((ctx.running_thread(), ctx.scheduler.clone())));
+        let storage = ctx.storage;
+        let io = IoPosition::of(ctx);
+
+        let result = ctx.step();
+        if result.is_err() {
+            ctx.instruction_pointer = instruction_pointer;
+            ctx.tape_pos = tape_pos;
+            return result;
+        }
+
+        let ip_stack = IpStackChange::between(stack_len, stack_top, &ctx.ip_stack);
+        let call_stack = IpStackChange::between(calls_len, calls_top, &ctx.call_stack);
+        if self.records.len() == self.record_capacity {
+            self.records.pop_front();
+        }
+        if self.record_capacity > 0 {
+            self.records.push_back(UndoRecord {
+                instruction_pointer,
+                tape_pos,
+                ip_stack,
+                call_stack,
+                procedure,
+                threads,
+                storage,
+                io,
+                cells
+            });
+        }
+        self.step += 1;
+        result
+    }
+
+    /// Reverts the last executed step of `ctx`.
+    /// Returns the amount of steps actually gone back, which is more than one when the state
+    /// has been restored from a snapshot, or `None` if there is no history left.
+    pub fn step_back<I: Read, O: Write>(&mut self, ctx: &mut Machine<I, O>) -> Option<u64> {
+        if let Some(record) = self.records.pop_back() {
+            if let Some(threads) = record.threads {
+                let (running, scheduler) = *threads;
+                ctx.switch_to(running);
+                ctx.scheduler = scheduler;
+            } else {
+                ctx.instruction_pointer = record.instruction_pointer;
+                ctx.tape_pos = record.tape_pos;
+                record.ip_stack.revert(&mut ctx.ip_stack);
+                record.call_stack.revert(&mut ctx.call_stack);
+            }
+            if let Some((number, body)) = record.procedure {
+                ctx.procedures[number] = body;
+            }
+            ctx.storage = record.storage;
+            record.io.restore(ctx);
+            for (pos, value) in record.cells.iter().rev().flatten() {
+                ctx.tape[*pos] = *value;
+            }
+            self.step -= 1;
+            // snapshots made after this point do not describe our past anymore
+            while self.snapshots.back().is_some_and(|it| it.step > self.step) {
+                self.snapshots.pop_back();
+            }
+            return Some(1);
+        }
+
+        while let Some(snapshot) = self.snapshots.pop_back() {
+            if snapshot.step >= self.step {
+                continue;
+            }
+            let gone_back = self.step - snapshot.step;
+            ctx.tape[..snapshot.tape.len()].copy_from_slice(&snapshot.tape);
+            ctx.tape[snapshot.tape.len()..].fill(0);
+            ctx.tape_pos = snapshot.tape_pos;
+            ctx.instruction_pointer = snapshot.instruction_pointer;
+            ctx.ip_stack.clone_from(&snapshot.ip_stack);
+            ctx.call_stack.clone_from(&snapshot.call_stack);
+            ctx.procedures = snapshot.procedures;
+            ctx.storage = snapshot.storage;
+            snapshot.io.restore(ctx);
+            ctx.scheduler.clone_from(&snapshot.scheduler);
+            self.step = snapshot.step;
+            // keep it around, so the debugger can return here again
+            self.snapshots.push_back(snapshot);
+            return Some(gone_back);
+        }
+        None
+    }
+
+    fn take_snapshot<I: Read, O: Write>(&mut self, ctx: &Machine<I, O>) {
+        if self.snapshots.back().is_some_and(|it| it.step == self.step) {
+            return;
+        }
+        if self.snapshots.len() == self.snapshot_capacity {
+            self.snapshots.pop_front();
+        }
+        let used_len = ctx.tape.iter().rposition(|&cell| cell != 0).map_or(0, |pos| pos + 1);
+        self.snapshots.push_back(Snapshot {
+            step: self.step,
+            tape: ctx.tape[..used_len].to_vec(),
+            tape_pos: ctx.tape_pos,
+            instruction_pointer: ctx.instruction_pointer,
+            ip_stack: ctx.ip_stack.clone(),
+            call_stack: ctx.call_stack.clone(),
+            procedures: ctx.procedures,
+            storage: ctx.storage,
+            io: IoPosition::of(ctx),
+            scheduler: ctx.scheduler.clone()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use crate::{Config, Error, Extensions, Machine, Status};
+    use super::History;
+
+    fn parse(code: &str) -> Machine<&'static [u8], Vec<u8>> {
+        let extensions = Extensions { procedures: true, fork: true, extended: true, ..Extensions::default() };
+        Machine::parse(code, Config { tape_size: 16, extensions, ..Config::default() }, &b""[..], Vec::new()).unwrap()
+    }
+
+    type State = (Vec<u8>, usize, usize, Vec<usize>, Vec<usize>, (usize, usize), u8);
+
+    fn state<I: Read, O: Write>(ctx: &Machine<I, O>) -> State {
+        let stacks = (ctx.ip_stack().to_vec(), ctx.call_stack().to_vec());
+        let threads = (ctx.thread(), ctx.threads());
+        (ctx.tape().to_vec(), ctx.tape_pos(), ctx.instruction_pointer(), stacks.0, stacks.1, threads, ctx.storage())
+    }
+
+    #[test]
+    fn step_back_restores_every_step() {
+        let programs = [
+            "++[->+>++[-<+>]<<]>>[-]+++<<-",
+            "+(>[-<:>]<)>+++<:-(+)::",
+            ">>+<<Y[->[><]<<.>]+++++++>>-",
+            "+++$>!{^~}&|$+$<[-(+$@):]"
+        ];
+        for code in programs {
+            let mut ctx = parse(code);
+            let mut history = History::new(1024, 1024, 4);
+            let mut states = vec![state(&ctx)];
+            while let Status::Running = history.step(&mut ctx).unwrap() {
+                states.push(state(&ctx));
+            }
+            while let Some(expected) = states.pop() {
+                assert_eq!(Some(1), history.step_back(&mut ctx));
+                assert_eq!(expected, state(&ctx));
+            }
+            assert_eq!(None, history.step_back(&mut ctx));
+            assert_eq!(0, history.current_step());
+        }
+    }
+
+    #[test]
+    fn step_back_falls_back_to_snapshots() {
+        let mut ctx = parse("+++++[->++<]>>+<<");
+        let mut history = History::new(2, 4, 16);
+        let mut states = vec![state(&ctx)];
+        while let Status::Running = history.step(&mut ctx).unwrap() {
+            states.push(state(&ctx));
+        }
+        let total = history.current_step();
+
+        assert_eq!(Some(1), history.step_back(&mut ctx));
+        assert_eq!(Some(1), history.step_back(&mut ctx));
+        let snapshot_step = (total - 3) / 4 * 4;
+        assert_eq!(Some(total - 2 - snapshot_step), history.step_back(&mut ctx));
+        assert_eq!(states[snapshot_step as usize], state(&ctx));
+
+        assert_eq!(Some(4), history.step_back(&mut ctx));
+        assert_eq!(states[snapshot_step as usize - 4], state(&ctx));
+    }
+
+    #[test]
+    fn steps_taken_again_replay_the_same_io() {
+        let programs = [(",.>,+[-.,+]", false), (",;,;;;+;", true)];
+        for (code, boolfuck) in programs {
+            let config = Config { tape_size: 16, boolfuck, ..Config::default() };
+            let mut ctx = Machine::parse(code, config, &b"abc"[..], Vec::new()).unwrap();
+            let mut history = History::new(1024, 4, 4);
+            let mut states = vec![state(&ctx)];
+            while let Status::Running = history.step(&mut ctx).unwrap() {
+                states.push(state(&ctx));
+            }
+            let output = ctx.output_mut().clone();
+
+            for _ in 0..2 {
+                while history.step_back(&mut ctx).is_some() {}
+                assert_eq!(states[0], state(&ctx));
+                for expected in &states[1..] {
+                    history.step(&mut ctx).unwrap();
+                    assert_eq!(*expected, state(&ctx));
+                }
+            }
+            assert_eq!(output, ctx.into_output(), "{code}");
+        }
+    }
+
+    #[test]
+    fn faulty_step_is_not_recorded() {
+        let mut ctx = parse("+>+<<");
+        let mut history = History::new(16, 16, 1);
+        let mut result = Ok(Status::Running);
+        while let Ok(Status::Running) = result {
+            result = history.step(&mut ctx);
+        }
+        assert!(matches!(result, Err(Error::TapeUnderflow { instruction_pointer: 3 })));
+        assert_eq!(3, history.current_step());
+        assert_eq!(Some(1), history.step_back(&mut ctx));
+        assert_eq!((2, 1), (ctx.instruction_pointer(), ctx.tape_pos()));
+    }
+}
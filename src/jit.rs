@@ -0,0 +1,560 @@
+use std::io::{Read, Write};
+use brain_fuck_parser::SimOperation;
+
+use crate::{Config, EOF_VALUE, Error, MAX_CALL_DEPTH};
+use crate::machine::dump_tape;
+
+/// Exit codes of the generated function, stored in the three lowest bits of its result.
+/// The rest of the bits hold the instruction pointer of the faulty operation, followed by
+/// the number of the procedure in the lowest byte for `EXIT_UNDEFINED_PROCEDURE`.
+const EXIT_OK: u64 = 0;
+const EXIT_OVERFLOW: u64 = 1;
+const EXIT_UNDERFLOW: u64 = 2;
+const EXIT_IO_ERROR: u64 = 3;
+const EXIT_UNDEFINED_PROCEDURE: u64 = 4;
+const EXIT_CALL_STACK_OVERFLOW: u64 = 5;
+
+/// i/o state the generated code calls back into, with the tape for `jit_debug`.
+struct JitIo<'a> {
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    error: Option<std::io::Error>,
+    tape: *const u8,
+    tape_len: usize
+}
+
+extern "sysv64" fn jit_put_char(io: *mut JitIo, value: u32) -> u32 {
+    let io = unsafe { &mut *io };
+    match io.output.write_all(&[value as u8]) {
+        Ok(()) => 0,
+        Err(err) => {
+            io.error = Some(err);
+            1
+        }
+    }
+}
+
+extern "sysv64" fn jit_get_char(io: *mut JitIo) -> i32 {
+    let io = unsafe { &mut *io };
+    let mut buf = [EOF_VALUE];
+    match io.input.read(&mut buf) {
+        Ok(0) => EOF_VALUE as i32,
+        Ok(_) => buf[0] as i32,
+        Err(err) => {
+            io.error = Some(err);
+            -1
+        }
+    }
+}
+
+extern "sysv64" fn jit_debug(io: *mut JitIo, cell: *const u8) -> u32 {
+    let io = unsafe { &mut *io };
+    if let Err(err) = io.output.flush() {
+        io.error = Some(err);
+        return 1;
+    }
+    let tape = unsafe { std::slice::from_raw_parts(io.tape, io.tape_len) };
+    eprintln!("{}", dump_tape(tape, cell as usize - io.tape as usize));
+    0
+}
+
+type Label = usize;
+
+/// Minimal x86-64 assembler: raw bytes plus rel32 jumps to labels patched at the end.
+///
+/// Register usage of the generated code:
+/// `rbx` points to the current cell, `r12` and `r13` are the tape bounds,
+/// `r14` holds the `JitIo` pointer passed to the i/o callbacks, `r15` the table of procedure
+/// addresses and `rbp` the stack pointer before any procedure call. The storage register is
+/// the byte at `rbp - 8`, in the slot keeping the stack aligned.
+#[derive(Default)]
+struct Assembler {
+    code: Vec<u8>,
+    labels: Vec<Option<usize>>,
+    fixups: Vec<(usize, Label)>
+}
+
+impl Assembler {
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn emit_u32(&mut self, value: u32) {
+        self.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        self.labels.len() - 1
+    }
+
+    fn bind(&mut self, label: Label) {
+        self.labels[label] = Some(self.code.len());
+    }
+
+    fn rel32(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.emit_u32(0);
+    }
+
+    fn jmp(&mut self, label: Label) {
+        self.emit(&[0xE9]);
+        self.rel32(label);
+    }
+
+    /// `jcc rel32`, `condition` being the low nibble of the opcode.
+    fn jcc(&mut self, condition: u8, label: Label) {
+        self.emit(&[0x0F, 0x80 | condition]);
+        self.rel32(label);
+    }
+
+    /// `cmp byte [rbx], 0`
+    fn test_cell(&mut self) {
+        self.emit(&[0x80, 0x3B, 0x00]);
+    }
+
+    /// `mov eax, ip; jmp fault`
+    fn fault(&mut self, ip: usize, fault: Label) {
+        self.emit(&[0xB8]);
+        self.emit_u32(ip as u32);
+        self.jmp(fault);
+    }
+
+    /// `mov rax, address; call rax` with `rdi` set to the `JitIo` pointer
+    fn call(&mut self, address: usize) {
+        self.emit(&[0x4C, 0x89, 0xF7]);
+        self.emit(&[0x48, 0xB8]);
+        self.code.extend_from_slice(&(address as u64).to_le_bytes());
+        self.emit(&[0xFF, 0xD0]);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for (at, label) in self.fixups.iter() {
+            let target = self.labels[*label].expect("every label is bound");
+            let rel = target as i64 - (*at as i64 + 4);
+            self.code[*at..*at + 4].copy_from_slice(&(rel as i32).to_le_bytes());
+        }
+        self.code
+    }
+}
+
+const JE: u8 = 0x4;
+const JNE: u8 = 0x5;
+const JB: u8 = 0x2;
+const JAE: u8 = 0x3;
+const JA: u8 = 0x7;
+const JS: u8 = 0x8;
+
+struct Exits {
+    /// Where the program ends without an error.
+    end: Label,
+    overflow: Label,
+    underflow: Label,
+    io_error: Label,
+    undefined_procedure: Label,
+    call_stack_overflow: Label
+}
+
+/// Executable memory holding the machine code, unmapped on drop.
+struct ExecutableBuffer {
+    ptr: *mut libc::c_void,
+    len: usize
+}
+
+impl ExecutableBuffer {
+    /// Fails with `Error::Unsupported` where memory can't be mapped or made executable, e.g.
+    /// on kernels enforcing W^X or under seccomp.
+    fn new(code: &[u8]) -> Result<Self, Error> {
+        let unsupported = Error::Unsupported { feature: "executable memory", backend: "JIT" };
+        let len = code.len().max(1);
+        unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0
+            );
+            if ptr == libc::MAP_FAILED {
+                return Err(unsupported);
+            }
+            // Unmapped on drop from now on.
+            let buffer = Self { ptr, len };
+            std::ptr::copy_nonoverlapping(code.as_ptr(), ptr as *mut u8, code.len());
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return Err(unsupported);
+            }
+            Ok(buffer)
+        }
+    }
+}
+
+impl Drop for ExecutableBuffer {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len); }
+    }
+}
+
+type JitFunction = extern "sysv64" fn(tape: *mut u8, tape_len: usize, io: *mut JitIo, procedures: *mut usize) -> u64;
+
+/// Bytecode compiled to x86-64 machine code.
+///
+/// Loops become native conditional jumps, procedures native calls, `,` and `.` call back into
+/// Rust, and every tape pointer move is bounds checked, reporting the same errors as the
+/// interpreters. Programs forking with `SimOperation::Fork` are rejected, as is every program
+/// where executable memory is not available; `run_with_io` runs them on `Machine` instead.
+pub struct JitProgram {
+    buffer: ExecutableBuffer
+}
+
+impl JitProgram {
+    pub fn compile(ops: &[SimOperation]) -> Result<Self, Error> {
+        if ops.contains(&SimOperation::Fork) {
+            return Err(Error::Unsupported { feature: "`Y` threads", backend: "JIT" });
+        }
+        let mut asm = Assembler::default();
+        let exits = Exits {
+            end: asm.new_label(),
+            overflow: asm.new_label(),
+            underflow: asm.new_label(),
+            io_error: asm.new_label(),
+            undefined_procedure: asm.new_label(),
+            call_stack_overflow: asm.new_label()
+        };
+        let exit = asm.new_label();
+
+        // push rbp, rbx, r12, r13, r14, r15; mov rbp, rsp;
+        // sub rsp, 8, keeping the stack 16 byte aligned for the calls
+        asm.emit(&[0x55, 0x53, 0x41, 0x54, 0x41, 0x55, 0x41, 0x56, 0x41, 0x57]);
+        asm.emit(&[0x48, 0x89, 0xE5, 0x48, 0x83, 0xEC, 0x08]);
+        // mov rbx, rdi; mov r12, rdi; lea r13, [rdi + rsi]; mov r14, rdx; mov r15, rcx
+        asm.emit(&[0x48, 0x89, 0xFB, 0x49, 0x89, 0xFC, 0x4C, 0x8D, 0x2C, 0x37, 0x49, 0x89, 0xD6, 0x49, 0x89, 0xCF]);
+        // mov byte [rbp - 8], 0, clearing the storage register
+        asm.emit(&[0xC6, 0x45, 0xF8, 0x00]);
+
+        Self::compile_block(&mut asm, ops, 0, &exits);
+
+        // xor eax, eax
+        asm.bind(exits.end);
+        asm.emit(&[0x31, 0xC0]);
+        asm.bind(exit);
+        // mov rsp, rbp, leaving the procedures a fault happened in; pop r15, r14, r13, r12, rbx, rbp; ret
+        asm.emit(&[0x48, 0x89, 0xEC]);
+        asm.emit(&[0x41, 0x5F, 0x41, 0x5E, 0x41, 0x5D, 0x41, 0x5C, 0x5B, 0x5D, 0xC3]);
+
+        // shl rax, 3; or rax, code; jmp exit
+        for (label, code) in [
+            (exits.overflow, EXIT_OVERFLOW),
+            (exits.underflow, EXIT_UNDERFLOW),
+            (exits.call_stack_overflow, EXIT_CALL_STACK_OVERFLOW)
+        ] {
+            asm.bind(label);
+            asm.emit(&[0x48, 0xC1, 0xE0, 0x03, 0x48, 0x83, 0xC8, code as u8]);
+            asm.jmp(exit);
+        }
+        // shl rax, 8; mov al, [rbx]; shl rax, 3; or rax, code; jmp exit
+        asm.bind(exits.undefined_procedure);
+        asm.emit(&[0x48, 0xC1, 0xE0, 0x08, 0x8A, 0x03]);
+        asm.emit(&[0x48, 0xC1, 0xE0, 0x03, 0x48, 0x83, 0xC8, EXIT_UNDEFINED_PROCEDURE as u8]);
+        asm.jmp(exit);
+        // mov eax, code; jmp exit
+        asm.bind(exits.io_error);
+        asm.emit(&[0xB8]);
+        asm.emit_u32(EXIT_IO_ERROR as u32);
+        asm.jmp(exit);
+
+        Ok(Self { buffer: ExecutableBuffer::new(&asm.finish())? })
+    }
+
+    /// Emits the operations starting at `start` up to the end of the enclosing loop, procedure
+    /// or the program. The bytecode keeps every loop and procedure body contiguous, ending
+    /// with `JnzRestoreIP` or `Return`.
+    fn compile_block(asm: &mut Assembler, ops: &[SimOperation], start: usize, exits: &Exits) {
+        for (ip, op) in ops.iter().enumerate().skip(start) {
+            match *op {
+                SimOperation::JnzRestoreIP { .. } | SimOperation::Return | SimOperation::EndProgram => break,
+                SimOperation::Noop => {}
+                SimOperation::DefineProcedure { target_ip } => {
+                    let body = asm.new_label();
+                    let end = asm.new_label();
+                    asm.jmp(end);
+                    asm.bind(body);
+                    // sub rsp, 8, realigning the stack after the call
+                    asm.emit(&[0x48, 0x83, 0xEC, 0x08]);
+                    Self::compile_block(asm, ops, target_ip as usize, exits);
+                    // add rsp, 8; ret
+                    asm.emit(&[0x48, 0x83, 0xC4, 0x08, 0xC3]);
+                    asm.bind(end);
+                    // movzx eax, byte [rbx]; lea rcx, [rip + body]; mov [r15 + rax * 8], rcx
+                    asm.emit(&[0x0F, 0xB6, 0x03, 0x48, 0x8D, 0x0D]);
+                    asm.rel32(body);
+                    asm.emit(&[0x49, 0x89, 0x0C, 0xC7]);
+                }
+                SimOperation::CallProcedure => {
+                    let defined = asm.new_label();
+                    let within_limit = asm.new_label();
+                    // movzx eax, byte [rbx]; mov rax, [r15 + rax * 8]; test rax, rax
+                    asm.emit(&[0x0F, 0xB6, 0x03, 0x49, 0x8B, 0x04, 0xC7, 0x48, 0x85, 0xC0]);
+                    asm.jcc(JNE, defined);
+                    asm.fault(ip, exits.undefined_procedure);
+                    asm.bind(defined);
+                    // Every call takes 16 bytes of stack. lea rcx, [rbp - limit]; cmp rsp, rcx
+                    asm.emit(&[0x48, 0x8D, 0x8D]);
+                    asm.emit_u32((8 + 16 * MAX_CALL_DEPTH as i32).wrapping_neg() as u32);
+                    asm.emit(&[0x48, 0x39, 0xCC]);
+                    asm.jcc(JA, within_limit);
+                    asm.fault(ip, exits.call_stack_overflow);
+                    asm.bind(within_limit);
+                    // call rax
+                    asm.emit(&[0xFF, 0xD0]);
+                }
+                SimOperation::JnzSaveIP { target_ip } => {
+                    let body = asm.new_label();
+                    let end = asm.new_label();
+                    asm.test_cell();
+                    asm.jcc(JE, end);
+                    asm.bind(body);
+                    Self::compile_block(asm, ops, target_ip as usize, exits);
+                    asm.test_cell();
+                    asm.jcc(JNE, body);
+                    asm.bind(end);
+                }
+                SimOperation::Inc(amount) => asm.emit(&[0x80, 0x03, amount]),
+                SimOperation::Dec(amount) => asm.emit(&[0x80, 0x2B, amount]),
+                SimOperation::IncTapePos(offset) => {
+                    // add rbx, offset
+                    asm.emit(&[0x48, 0x81, 0xC3]);
+                    asm.emit_u32(offset);
+                    Self::check_upper_bound(asm, ip, exits, 0xEB);
+                }
+                SimOperation::DecTapePos(offset) => {
+                    // sub rbx, offset
+                    asm.emit(&[0x48, 0x81, 0xEB]);
+                    asm.emit_u32(offset);
+                    Self::check_lower_bound(asm, ip, exits, 0xE3);
+                }
+                SimOperation::IncTapePosUntilEmpty | SimOperation::DecTapePosUntilEmpty => {
+                    let scan = asm.new_label();
+                    let done = asm.new_label();
+                    asm.bind(scan);
+                    asm.test_cell();
+                    asm.jcc(JE, done);
+                    if let SimOperation::IncTapePosUntilEmpty = op {
+                        // inc rbx
+                        asm.emit(&[0x48, 0xFF, 0xC3]);
+                        Self::check_upper_bound(asm, ip, exits, 0xEB);
+                    } else {
+                        // dec rbx
+                        asm.emit(&[0x48, 0xFF, 0xCB]);
+                        Self::check_lower_bound(asm, ip, exits, 0xE3);
+                    }
+                    asm.jmp(scan);
+                    asm.bind(done);
+                }
+                SimOperation::PutChar => {
+                    // movzx esi, byte [rbx]
+                    asm.emit(&[0x0F, 0xB6, 0x33]);
+                    asm.call(jit_put_char as *const () as usize);
+                    // test eax, eax
+                    asm.emit(&[0x85, 0xC0]);
+                    asm.jcc(JNE, exits.io_error);
+                }
+                SimOperation::GetChar => {
+                    asm.call(jit_get_char as *const () as usize);
+                    // test eax, eax
+                    asm.emit(&[0x85, 0xC0]);
+                    asm.jcc(JS, exits.io_error);
+                    // mov [rbx], al
+                    asm.emit(&[0x88, 0x03]);
+                }
+                SimOperation::Debug => {
+                    // mov rsi, rbx
+                    asm.emit(&[0x48, 0x89, 0xDE]);
+                    asm.call(jit_debug as *const () as usize);
+                    // test eax, eax
+                    asm.emit(&[0x85, 0xC0]);
+                    asm.jcc(JNE, exits.io_error);
+                }
+                SimOperation::Fork => unreachable!(),
+                SimOperation::End => asm.jmp(exits.end),
+                // mov al, [rbx]; mov [rbp - 8], al
+                SimOperation::Store => asm.emit(&[0x8A, 0x03, 0x88, 0x45, 0xF8]),
+                // mov al, [rbp - 8]; mov [rbx], al
+                SimOperation::Retrieve => asm.emit(&[0x8A, 0x45, 0xF8, 0x88, 0x03]),
+                // shl byte [rbx], 1
+                SimOperation::ShiftLeft => asm.emit(&[0xD0, 0x23]),
+                // shr byte [rbx], 1
+                SimOperation::ShiftRight => asm.emit(&[0xD0, 0x2B]),
+                // not byte [rbx]
+                SimOperation::Not => asm.emit(&[0xF6, 0x13]),
+                // mov al, [rbp - 8]; xor/and/or [rbx], al
+                SimOperation::Xor => asm.emit(&[0x8A, 0x45, 0xF8, 0x30, 0x03]),
+                SimOperation::And => asm.emit(&[0x8A, 0x45, 0xF8, 0x20, 0x03]),
+                SimOperation::Or => asm.emit(&[0x8A, 0x45, 0xF8, 0x08, 0x03]),
+                SimOperation::Clear => asm.emit(&[0xC6, 0x03, 0x00]),
+                SimOperation::AddToTheRightAndClear(offset) |
+                SimOperation::DecFromTheRightAndClear(offset) => {
+                    // lea rax, [rbx + offset]
+                    asm.emit(&[0x48, 0x8D, 0x83]);
+                    asm.emit_u32(offset);
+                    Self::check_upper_bound(asm, ip, exits, 0xE8);
+                    Self::transfer_and_clear(asm, matches!(op, SimOperation::AddToTheRightAndClear(_)));
+                }
+                SimOperation::AddToTheLeftAndClear(offset) |
+                SimOperation::DecFromTheLeftAndClear(offset) => {
+                    let skip = asm.new_label();
+                    asm.test_cell();
+                    asm.jcc(JE, skip);
+                    // lea rax, [rbx - offset]
+                    asm.emit(&[0x48, 0x8D, 0x83]);
+                    asm.emit_u32((offset as i32).wrapping_neg() as u32);
+                    Self::check_lower_bound(asm, ip, exits, 0xE0);
+                    Self::transfer_and_clear(asm, matches!(op, SimOperation::AddToTheLeftAndClear(_)));
+                    asm.bind(skip);
+                }
+            }
+        }
+    }
+
+    /// `cmp reg, r13; jb ok; fault`, `modrm` selecting the register compared
+    fn check_upper_bound(asm: &mut Assembler, ip: usize, exits: &Exits, modrm: u8) {
+        let ok = asm.new_label();
+        asm.emit(&[0x4C, 0x39, modrm]);
+        asm.jcc(JB, ok);
+        asm.fault(ip, exits.overflow);
+        asm.bind(ok);
+    }
+
+    /// `cmp reg, r12; jae ok; fault`, `modrm` selecting the register compared
+    fn check_lower_bound(asm: &mut Assembler, ip: usize, exits: &Exits, modrm: u8) {
+        let ok = asm.new_label();
+        asm.emit(&[0x4C, 0x39, modrm]);
+        asm.jcc(JAE, ok);
+        asm.fault(ip, exits.underflow);
+        asm.bind(ok);
+    }
+
+    /// `mov cl, [rbx]; add/sub [rax], cl; mov byte [rbx], 0`
+    fn transfer_and_clear(asm: &mut Assembler, add: bool) {
+        asm.emit(&[0x8A, 0x0B]);
+        asm.emit(&[if add { 0x00 } else { 0x28 }, 0x08]);
+        asm.emit(&[0xC6, 0x03, 0x00]);
+    }
+
+    /// Runs the program on a fresh tape, flushing the output afterwards.
+    pub fn run<I: Read, O: Write>(&self, config: Config, mut input: I, mut output: O) -> Result<(), Error> {
+        let mut tape = vec![0u8; config.tape_size];
+        let mut io = JitIo {
+            input: &mut input,
+            output: &mut output,
+            error: None,
+            tape: tape.as_ptr(),
+            tape_len: tape.len()
+        };
+        let function: JitFunction = unsafe { std::mem::transmute(self.buffer.ptr) };
+        let mut procedures = [0usize; 256];
+        let result = function(tape.as_mut_ptr(), tape.len(), &mut io, procedures.as_mut_ptr());
+
+        let instruction_pointer = (result >> 3) as usize;
+        match result & 7 {
+            EXIT_OVERFLOW => return Err(Error::TapeOverflow { instruction_pointer }),
+            EXIT_UNDERFLOW => return Err(Error::TapeUnderflow { instruction_pointer }),
+            EXIT_UNDEFINED_PROCEDURE => {
+                return Err(Error::UndefinedProcedure {
+                    procedure: instruction_pointer as u8,
+                    instruction_pointer: instruction_pointer >> 8
+                })
+            }
+            EXIT_CALL_STACK_OVERFLOW => return Err(Error::CallStackOverflow { instruction_pointer }),
+            EXIT_IO_ERROR => return Err(io.error.take().expect("i/o callback stores its error").into()),
+            EXIT_OK => {}
+            _ => unreachable!()
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brain_fuck_parser::parse_bf;
+    use crate::{Config, Error, Machine};
+    use super::JitProgram;
+
+    fn run_jit(code: &str, config: Config, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        JitProgram::compile(&parse_bf(code).compile_bytecode()).unwrap().run(config, input, &mut output)?;
+        Ok(output)
+    }
+
+    fn run_switch(code: &str, config: Config, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut machine = Machine::parse(code, config, input, Vec::new())?;
+        machine.run()?;
+        Ok(machine.into_output())
+    }
+
+    #[test]
+    fn output_matches_switch_backend() {
+        let programs = [
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+            ",+[-.,+]",
+            ">>++++[<++++[<++++>-]>-]<<.[-]++[>+++<-]>[<+>-]<.>+++++[->+>++<<]>>[-<<->>]<<.[.-]",
+            "++++[>++++[>++++<-]<-]>>[<<+>>-]<<[>+>+<<-]>>.<.<[-]>>>++[<<+>[<+>-]>-]<<<.>.",
+            "+++++[>>+++<<-]>>[<<->>-]<<[>>>+<<<+]>>>.<<<[->>>>-<<<<]>>>>.",
+            "-[>+<-----]>--.>++[>+>+[<]>>-]<.>.,.[<]>."
+        ];
+        for code in programs {
+            let config = Config::default();
+            assert_eq!(
+                run_switch(code, config, b"jit").unwrap(),
+                run_jit(code, config, b"jit").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn tape_bounds_are_checked() {
+        let config = Config { tape_size: 4, ..Config::default() };
+        assert!(matches!(
+            run_jit("+[>+]", config, b""),
+            Err(Error::TapeOverflow { instruction_pointer: 3 })
+        ));
+        assert!(matches!(
+            run_jit("><<", config, b""),
+            Err(Error::TapeUnderflow { instruction_pointer: 1 })
+        ));
+        assert!(matches!(
+            run_jit("+>+>+>+[<]", config, b""),
+            Err(Error::TapeUnderflow { .. })
+        ));
+        assert!(matches!(
+            run_jit("+>+>+>+[>]", config, b""),
+            Err(Error::TapeOverflow { .. })
+        ));
+        assert!(matches!(
+            run_jit(">>>+[->+<]", config, b""),
+            Err(Error::TapeOverflow { instruction_pointer: 2 })
+        ));
+        assert!(matches!(
+            run_jit("+[-<+>]", config, b""),
+            Err(Error::TapeUnderflow { instruction_pointer: 1 })
+        ));
+    }
+
+    #[test]
+    fn io_errors_are_reported() {
+        struct BrokenPipe;
+        impl std::io::Write for BrokenPipe {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let ops = parse_bf("+.").compile_bytecode();
+        let result = JitProgram::compile(&ops).unwrap().run(Config::default(), &b""[..], BrokenPipe);
+        assert!(matches!(result, Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::BrokenPipe));
+    }
+}
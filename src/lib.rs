@@ -0,0 +1,154 @@
+mod error;
+mod machine;
+mod threaded;
+#[cfg(all(target_arch = "x86_64", unix))]
+mod jit;
+pub mod history;
+pub mod debugger;
+pub mod emit;
+
+use std::io::{Read, Write};
+pub use brain_fuck_parser::{
+    Dialect, DialectError, Evaluation, Extensions, FormatOptions, Node, ParseError, SimOperation, evaluate, format, minify,
+    partially_evaluate, split_inline_input, try_parse_bf, try_parse_bf_source, try_parse_bf_with, try_parse_bf_with_input,
+    try_parse_boolfuck, try_parse_dialect
+};
+pub use error::Error;
+pub use machine::{Backend, Config, EOF_VALUE, MAX_CALL_DEPTH, Machine, Status};
+pub use threaded::ThreadedProgram;
+#[cfg(all(target_arch = "x86_64", unix))]
+pub use jit::JitProgram;
+
+/// Applies the transformations enabled in `config` to the parsed program.
+pub fn prepare(program: Node, config: &Config) -> Node {
+    match config.evaluate_prefix {
+        // The evaluation works on 8-bit cells.
+        _ if config.boolfuck => program,
+        0 => program,
+        fuel => partially_evaluate(&program, config.tape_size, fuel)
+    }
+}
+
+/// Parses and runs the program with the backend chosen in `config`,
+/// reading `,` from `input` and writing `.` to `output`. Programs forking threads and Boolfuck
+/// programs always run on `Machine`, the only backend with a scheduler and single-bit cells, as
+/// do programs for the JIT where it can't map executable memory.
+pub fn run_with_io<I: Read, O: Write>(code: &str, config: Config, input: I, output: O) -> Result<(), Error> {
+    let program = prepare(machine::parse_program(code, &config)?, &config);
+    if program.forks() || config.boolfuck {
+        return Machine::new(&program, config, input, output).run();
+    }
+    match config.backend {
+        Backend::Switch => Machine::new(&program, config, input, output).run(),
+        Backend::Threaded => ThreadedProgram::compile(&program.compile_bytecode())?.run(config, input, output),
+        #[cfg(all(target_arch = "x86_64", unix))]
+        Backend::Jit => match JitProgram::compile(&program.compile_bytecode()) {
+            Ok(jit) => jit.run(config, input, output),
+            Err(Error::Unsupported { .. }) => Machine::new(&program, config, input, output).run(),
+            Err(err) => Err(err)
+        },
+        #[cfg(not(all(target_arch = "x86_64", unix)))]
+        Backend::Jit => Machine::new(&program, config, input, output).run()
+    }
+}
+
+/// Parses and runs the program on stdin and stdout.
+pub fn run(code: &str, config: Config) -> Result<(), Error> {
+    run_with_io(code, config, std::io::stdin().lock(), std::io::stdout().lock())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Backend, Config, Error, Extensions, ThreadedProgram, run_with_io, try_parse_bf_with};
+    use crate::emit::{Emit, emit, emit_rust};
+
+    #[test]
+    fn evaluated_prefix_runs_the_same_on_every_backend() {
+        let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.,+[-.,+]";
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            for evaluate_prefix in [0, 100, 1_000_000] {
+                let config = Config { backend, evaluate_prefix, ..Config::default() };
+                let mut output = Vec::new();
+                run_with_io(code, config, &b"io"[..], &mut output).unwrap();
+                assert_eq!(b"Hello World!\nio".to_vec(), output, "{config:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn debug_dumps_leave_the_output_alone() {
+        let code = "+++#[>++#<-]>.#";
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            let config = Config { backend, extensions: Extensions { debug: true, ..Extensions::default() }, ..Config::default() };
+            let mut output = Vec::new();
+            run_with_io(code, config, &b""[..], &mut output).unwrap();
+            assert_eq!(vec![6], output, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn procedures_run_the_same_on_every_backend() {
+        let extensions = Extensions { procedures: true, ..Extensions::default() };
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            let config = Config { backend, extensions, ..Config::default() };
+            let mut output = Vec::new();
+            run_with_io("(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:,(.+):", config, &b"io"[..], &mut output).unwrap();
+            assert_eq!(vec![3, 2, 1, b'A', b'i'], output, "{config:?}");
+
+            let undefined = run_with_io("(+)>+.:", config, &b""[..], Vec::new());
+            assert!(
+                matches!(undefined, Err(Error::UndefinedProcedure { procedure: 1, instruction_pointer: 4 })),
+                "{config:?}: {undefined:?}"
+            );
+            let overflow = run_with_io("+(>+<:):", config, &b""[..], Vec::new());
+            assert!(matches!(overflow, Err(Error::CallStackOverflow { .. })), "{config:?}: {overflow:?}");
+        }
+    }
+
+    #[test]
+    fn extended_operations_run_the_same_on_every_backend() {
+        let extensions = Extensions { procedures: true, extended: true, ..Extensions::default() };
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            for evaluate_prefix in [0, 1_000] {
+                let config = Config { backend, extensions, evaluate_prefix, ..Config::default() };
+                let mut output = Vec::new();
+                run_with_io("++++{$>+++^~}.<-!{|.,}.&.+(.@):+.", config, &b"A"[..], &mut output).unwrap();
+                assert_eq!(vec![122, 24, 32, 0, 1], output, "{config:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn forking_programs_run_on_the_interpreter() {
+        let extensions = Extensions { fork: true, ..Extensions::default() };
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            let config = Config { backend, extensions, ..Config::default() };
+            let mut output = Vec::new();
+            run_with_io("Y+.+.+.", config, &b""[..], &mut output).unwrap();
+            assert_eq!(vec![2, 1, 3, 2, 4, 3], output, "{config:?}");
+        }
+    }
+
+    #[test]
+    fn forking_programs_are_rejected_by_compiling_backends() {
+        let program = try_parse_bf_with("+[Y-]", Extensions { fork: true, ..Extensions::default() }).unwrap();
+        let unsupported = |result: Result<_, Error>| matches!(result, Err(Error::Unsupported { .. }));
+        assert!(unsupported(ThreadedProgram::compile(&program.compile_bytecode()).map(|_| ())));
+        #[cfg(all(target_arch = "x86_64", unix))]
+        assert!(unsupported(crate::JitProgram::compile(&program.compile_bytecode()).map(|_| ())));
+        for format in [Emit::Asm, Emit::C, Emit::Llvm, Emit::Rust, Emit::Wasm, Emit::Wat] {
+            assert!(unsupported(emit(&program, format, &Config::default()).map(|_| ())), "{format:?}");
+        }
+        assert!(unsupported(emit_rust(&program, &Config::default(), "fork").map(|_| ())));
+    }
+
+    #[test]
+    fn boolfuck_programs_run_on_the_interpreter() {
+        for backend in [Backend::Switch, Backend::Threaded, Backend::Jit] {
+            let config = Config { backend, boolfuck: true, evaluate_prefix: 1_000, ..Config::default() };
+            let mut output = Vec::new();
+            run_with_io(&format!("+;+;;;;;+;+;{}", ",;".repeat(8)), config, &b"B"[..], &mut output).unwrap();
+            assert_eq!(b"AB".to_vec(), output, "{config:?}");
+        }
+    }
+}
@@ -0,0 +1,753 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use brain_fuck_parser::{DEBUG_WINDOW, Extensions, Node, ParseError, SimOperation, try_parse_bf_with, try_parse_boolfuck};
+
+use crate::Error;
+
+/// Value stored to the cell by `,` once the input is exhausted, same as `getchar` returning EOF.
+pub const EOF_VALUE: u8 = 0xFF;
+
+/// Procedure calls that may be nested at once, the same on every backend.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// Execution engine used by `run` and `run_with_io`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Backend {
+    /// `Machine`, a `match` over the bytecode. The only one which supports stepping.
+    Switch,
+    /// `ThreadedProgram`, bytecode compiled into pre-bound closures.
+    Threaded,
+    /// `JitProgram`, bytecode compiled to x86-64 machine code.
+    /// Falls back to `Switch` on platforms other than x86-64 unix.
+    Jit
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "switch" => Ok(Backend::Switch),
+            "threaded" => Ok(Backend::Threaded),
+            "jit" => Ok(Backend::Jit),
+            _ => Err(format!("unknown backend {s}, expected one of: switch, threaded, jit"))
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Config {
+    pub tape_size: usize,
+    pub backend: Backend,
+    /// Language extensions the program is parsed with.
+    pub extensions: Extensions,
+    /// Steps the part of the program that runs before reading input may take to be evaluated
+    /// ahead of time, see `partially_evaluate`. 0 disables the evaluation.
+    pub evaluate_prefix: u64,
+    /// Steps a thread of a program forking with `Node::Fork` takes before the next one runs.
+    pub time_slice: usize,
+    /// Seed of the schedule of threads: 0 gives every thread slices of `time_slice` steps,
+    /// other values slices of 1 to `time_slice` steps drawn from a generator seeded with it,
+    /// so running with the same seed replays the same schedule.
+    pub schedule_seed: u64,
+    /// Parse programs as Boolfuck, see `try_parse_boolfuck`, and run them on single-bit cells,
+    /// reading and writing bytes one bit at a time, least significant bit first. Only
+    /// `Machine` runs them, and without `evaluate_prefix`.
+    pub boolfuck: bool
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tape_size: 0x100000,
+            backend: Backend::Switch,
+            extensions: Extensions::default(),
+            evaluate_prefix: 0,
+            time_slice: 1,
+            schedule_seed: 0,
+            boolfuck: false
+        }
+    }
+}
+
+/// Parses the program in the language of `config`.
+pub(crate) fn parse_program(code: &str, config: &Config) -> Result<Node, ParseError> {
+    if config.boolfuck {
+        try_parse_boolfuck(code, config.extensions)
+    } else {
+        try_parse_bf_with(code, config.extensions)
+    }
+}
+
+/// What `Node::Debug` prints to stderr: the tape position and the cells around it.
+pub(crate) fn dump_tape(tape: &[u8], tape_pos: usize) -> String {
+    let Range { start, end } = debug_window(tape, tape_pos);
+    format!("tape_pos {tape_pos}, cells {start}..{end}: {:?}", &tape[start..end])
+}
+
+/// The cells up to `DEBUG_WINDOW` away from the tape position, shown by `dump_tape` and the
+/// debugger.
+pub(crate) fn debug_window(tape: &[u8], tape_pos: usize) -> Range<usize> {
+    tape_pos.saturating_sub(DEBUG_WINDOW)..(tape_pos + DEBUG_WINDOW + 1).min(tape.len())
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    Running,
+    Finished
+}
+
+/// Bytes being read and written one bit at a time by Boolfuck programs.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub(crate) struct Bits {
+    input: u8,
+    /// Bits of `input` not read yet.
+    input_len: u8,
+    output: u8,
+    /// Bits written to `output` so far.
+    output_len: u8
+}
+
+/// I/o of a machine stepped by `History`, so that steps taken again after stepping back
+/// read the same bytes and don't repeat the output.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct IoLog {
+    /// Every read so far, `None` at the end of the input.
+    input: Vec<Option<u8>>,
+    /// Reads the program has made, the following ones are replayed from `input`.
+    pub(crate) read: usize,
+    /// Bytes the program has written.
+    pub(crate) written: usize,
+    /// Bytes written to the output, the most `written` has been.
+    output_len: usize
+}
+
+/// A thread of a forking program waiting for its turn.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Thread {
+    id: usize,
+    tape_pos: usize,
+    instruction_pointer: usize,
+    ip_stack: Vec<usize>,
+    call_stack: Vec<usize>
+}
+
+/// Round-robin scheduler of the threads started by `SimOperation::Fork`: the running thread
+/// takes a time slice of steps, then joins the back of the queue of waiting threads and the
+/// first of them runs. Forked threads join the back of the queue as well.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Scheduler {
+    waiting: VecDeque<Thread>,
+    /// Id of the running thread, threads being numbered in the order they're forked.
+    running: usize,
+    forked: usize,
+    /// Steps the running thread may still take before yielding.
+    slice_left: usize,
+    time_slice: usize,
+    /// State of the xorshift generator drawing the length of slices, 0 if they all take
+    /// `time_slice` steps.
+    seed: u64
+}
+
+impl Scheduler {
+    fn new(config: &Config) -> Self {
+        Self {
+            waiting: VecDeque::new(),
+            running: 0,
+            forked: 0,
+            slice_left: 0,
+            time_slice: config.time_slice.max(1),
+            seed: config.schedule_seed
+        }
+    }
+
+    fn next_slice(&mut self) -> usize {
+        if self.seed == 0 {
+            return self.time_slice;
+        }
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        1 + (self.seed % self.time_slice as u64) as usize
+    }
+}
+
+/// Bytecode interpreter state: the tape, the program and its i/o.
+pub struct Machine<I: Read, O: Write> {
+    pub(crate) tape: Vec<u8>,
+    pub(crate) tape_pos: usize,
+    pub(crate) instruction_pointer: usize,
+    pub(crate) ip_stack: Vec<usize>,
+    pub(crate) call_stack: Vec<usize>,
+    /// Start of the body of every procedure defined so far, by number.
+    pub(crate) procedures: [Option<usize>; 256],
+    /// Register of `Node::Store`, shared by all threads.
+    pub(crate) storage: u8,
+    /// Threads other than the running one, whose state is in the fields above.
+    pub(crate) scheduler: Scheduler,
+    /// Bits kept of every cell, 1 with `Config::boolfuck` and 8 otherwise.
+    cell_mask: u8,
+    /// Partial bytes of i/o with `Config::boolfuck`.
+    pub(crate) bits: Option<Bits>,
+    /// Set by `History` to replay the i/o of the steps it has reverted.
+    pub(crate) io_log: Option<Box<IoLog>>,
+    /// Whether the program forks, so that `run` needs the scheduler.
+    forks: bool,
+    ops: Vec<SimOperation>,
+    input: I,
+    output: O
+}
+
+impl<I: Read, O: Write> Machine<I, O> {
+    pub fn new(program: &Node, config: Config, input: I, output: O) -> Self {
+        Self {
+            tape: vec![0; config.tape_size],
+            tape_pos: 0,
+            instruction_pointer: 0,
+            ip_stack: Vec::new(),
+            call_stack: Vec::new(),
+            procedures: [None; 256],
+            storage: 0,
+            scheduler: Scheduler::new(&config),
+            cell_mask: if config.boolfuck { 1 } else { u8::MAX },
+            bits: config.boolfuck.then(Bits::default),
+            io_log: None,
+            forks: program.forks(),
+            ops: program.compile_bytecode(),
+            input,
+            output
+        }
+    }
+
+    pub fn parse(code: &str, config: Config, input: I, output: O) -> Result<Self, Error> {
+        Ok(Self::new(&parse_program(code, &config)?, config, input, output))
+    }
+
+    pub fn tape(&self) -> &[u8] {
+        &self.tape
+    }
+
+    pub fn tape_pos(&self) -> usize {
+        self.tape_pos
+    }
+
+    pub fn instruction_pointer(&self) -> usize {
+        self.instruction_pointer
+    }
+
+    /// Return addresses of the loops entered so far, innermost last.
+    pub fn ip_stack(&self) -> &[usize] {
+        &self.ip_stack
+    }
+
+    /// Return addresses of the procedure calls made so far, innermost last.
+    pub fn call_stack(&self) -> &[usize] {
+        &self.call_stack
+    }
+
+    /// Value of the storage register of `Extensions::extended`.
+    pub fn storage(&self) -> u8 {
+        self.storage
+    }
+
+    /// Id of the running thread, 0 for the first one and following the order of the forks for
+    /// the others.
+    pub fn thread(&self) -> usize {
+        self.scheduler.running
+    }
+
+    /// Threads that haven't finished, the running one included.
+    pub fn threads(&self) -> usize {
+        self.scheduler.waiting.len() + 1
+    }
+
+    pub fn operations(&self) -> &[SimOperation] {
+        &self.ops
+    }
+
+    pub fn current_operation(&self) -> SimOperation {
+        self.ops[self.instruction_pointer]
+    }
+
+    pub fn output_mut(&mut self) -> &mut O {
+        &mut self.output
+    }
+
+    pub fn into_output(self) -> O {
+        self.output
+    }
+
+    /// Runs the program until it finishes, flushing the output afterwards. Programs which
+    /// never fork run without the scheduler.
+    pub fn run(&mut self) -> Result<(), Error> {
+        if self.forks {
+            while let Status::Running = self.step()? {}
+        } else {
+            while let Status::Running = self.execute::<false>()? {}
+            self.write_partial_byte()?;
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn underflow(&self) -> Error {
+        Error::TapeUnderflow { instruction_pointer: self.instruction_pointer }
+    }
+
+    #[inline(always)]
+    fn overflow(&self) -> Error {
+        Error::TapeOverflow { instruction_pointer: self.instruction_pointer }
+    }
+
+    #[inline(always)]
+    fn right_of(&self, offset: u32) -> Result<usize, Error> {
+        let pos = self.tape_pos + offset as usize;
+        if pos < self.tape.len() { Ok(pos) } else { Err(self.overflow()) }
+    }
+
+    #[inline(always)]
+    fn left_of(&self, offset: u32) -> Result<usize, Error> {
+        self.tape_pos.checked_sub(offset as usize).ok_or_else(|| self.underflow())
+    }
+
+    /// State of the running thread.
+    pub(crate) fn running_thread(&self) -> Thread {
+        Thread {
+            id: self.scheduler.running,
+            tape_pos: self.tape_pos,
+            instruction_pointer: self.instruction_pointer,
+            ip_stack: self.ip_stack.clone(),
+            call_stack: self.call_stack.clone()
+        }
+    }
+
+    /// Makes `thread` the running thread, returning the previous one.
+    pub(crate) fn switch_to(&mut self, thread: Thread) -> Thread {
+        Thread {
+            id: std::mem::replace(&mut self.scheduler.running, thread.id),
+            tape_pos: std::mem::replace(&mut self.tape_pos, thread.tape_pos),
+            instruction_pointer: std::mem::replace(&mut self.instruction_pointer, thread.instruction_pointer),
+            ip_stack: std::mem::replace(&mut self.ip_stack, thread.ip_stack),
+            call_stack: std::mem::replace(&mut self.call_stack, thread.call_stack)
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, Error> {
+        if let Some(log) = self.io_log.as_deref_mut() {
+            if let Some(&byte) = log.input.get(log.read) {
+                log.read += 1;
+                return Ok(byte);
+            }
+        }
+        let mut buf = [0];
+        let byte = (self.input.read(&mut buf)? != 0).then_some(buf[0]);
+        if let Some(log) = self.io_log.as_deref_mut() {
+            log.input.push(byte);
+            log.read += 1;
+        }
+        Ok(byte)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> Result<(), Error> {
+        match self.io_log.as_deref_mut() {
+            Some(log) if log.written < log.output_len => log.written += 1,
+            Some(log) => {
+                self.output.write_all(&[byte])?;
+                log.written += 1;
+                log.output_len = log.written;
+            }
+            None => self.output.write_all(&[byte])?
+        }
+        Ok(())
+    }
+
+    /// Reads the next bit of the input, 0 once it is exhausted.
+    fn read_bit(&mut self, mut bits: Bits) -> Result<u8, Error> {
+        if bits.input_len == 0 {
+            bits.input = self.read_byte()?.unwrap_or(0);
+            bits.input_len = 8;
+        }
+        let bit = bits.input & 1;
+        bits.input >>= 1;
+        bits.input_len -= 1;
+        self.bits = Some(bits);
+        Ok(bit)
+    }
+
+    /// Adds a bit to the output, writing every byte once it's complete.
+    fn write_bit(&mut self, mut bits: Bits, bit: u8) -> Result<(), Error> {
+        bits.output |= bit << bits.output_len;
+        bits.output_len += 1;
+        if bits.output_len == 8 {
+            self.write_byte(bits.output)?;
+            bits = Bits { output: 0, output_len: 0, ..bits };
+        }
+        self.bits = Some(bits);
+        Ok(())
+    }
+
+    /// Writes the last byte of a Boolfuck program, padded with zero bits.
+    fn write_partial_byte(&mut self) -> Result<(), Error> {
+        if let Some(bits) = self.bits.filter(|bits| bits.output_len > 0) {
+            self.write_byte(bits.output)?;
+            self.bits = Some(Bits { output: 0, output_len: 0, ..bits });
+        }
+        Ok(())
+    }
+
+    /// Cells which the operation at the current instruction pointer may write to.
+    /// Used by the undo log to remember their previous values.
+    pub(crate) fn cells_written_by(&self, op: SimOperation) -> [Option<usize>; 2] {
+        match op {
+            SimOperation::Inc(_) |
+            SimOperation::Dec(_) |
+            SimOperation::GetChar |
+            SimOperation::Clear |
+            SimOperation::Retrieve |
+            SimOperation::ShiftLeft |
+            SimOperation::ShiftRight |
+            SimOperation::Not |
+            SimOperation::Xor |
+            SimOperation::And |
+            SimOperation::Or => [Some(self.tape_pos), None],
+            SimOperation::AddToTheRightAndClear(offset) |
+            SimOperation::DecFromTheRightAndClear(offset) => {
+                [Some(self.tape_pos), self.right_of(offset).ok()]
+            }
+            SimOperation::AddToTheLeftAndClear(offset) |
+            SimOperation::DecFromTheLeftAndClear(offset) => {
+                [Some(self.tape_pos), self.left_of(offset).ok()]
+            }
+            SimOperation::Fork => [Some(self.tape_pos), self.right_of(1).ok()],
+            _ => [None, None]
+        }
+    }
+
+    /// Executes a single operation of the running thread, then lets the next thread run if
+    /// the time slice of this one is over or it has finished. The program finishes with the
+    /// last of its threads.
+    #[inline(always)]
+    pub fn step(&mut self) -> Result<Status, Error> {
+        let status = self.execute::<true>()?;
+        if self.scheduler.waiting.is_empty() {
+            if status == Status::Finished {
+                self.write_partial_byte()?;
+            }
+            return Ok(status);
+        }
+        self.scheduler.slice_left -= 1;
+        if status == Status::Finished || self.scheduler.slice_left == 0 {
+            let next = self.scheduler.waiting.pop_front().expect("there are waiting threads");
+            let previous = self.switch_to(next);
+            if status == Status::Running {
+                self.scheduler.waiting.push_back(previous);
+            }
+            self.scheduler.slice_left = self.scheduler.next_slice();
+        }
+        Ok(Status::Running)
+    }
+
+    /// Executes a single operation of the running thread. Only with `THREADS` does it fork and
+    /// end the other threads, which is all of the scheduler it touches.
+    #[inline(always)]
+    fn execute<const THREADS: bool>(&mut self) -> Result<Status, Error> {
+        let node = *unsafe { self.ops.get_unchecked(self.instruction_pointer) };
+        match node {
+            SimOperation::Inc(amount) => {
+                self.tape[self.tape_pos] = self.tape[self.tape_pos].wrapping_add(amount) & self.cell_mask;
+            }
+            SimOperation::Dec(amount) => {
+                self.tape[self.tape_pos] = self.tape[self.tape_pos].wrapping_sub(amount) & self.cell_mask;
+            }
+            SimOperation::IncTapePos(offset) => {
+                self.tape_pos = self.right_of(offset)?;
+            }
+            SimOperation::DecTapePos(offset) => {
+                self.tape_pos = self.left_of(offset)?;
+            }
+            SimOperation::IncTapePosUntilEmpty => {
+                while self.tape[self.tape_pos] != 0 { self.tape_pos = self.right_of(1)?; }
+            }
+            SimOperation::DecTapePosUntilEmpty => {
+                while self.tape[self.tape_pos] != 0 { self.tape_pos = self.left_of(1)?; }
+            }
+            SimOperation::PutChar => match self.bits {
+                Some(bits) => self.write_bit(bits, self.tape[self.tape_pos])?,
+                None => self.write_byte(self.tape[self.tape_pos])?
+            }
+            SimOperation::GetChar => {
+                self.tape[self.tape_pos] = match self.bits {
+                    Some(bits) => self.read_bit(bits)?,
+                    None => self.read_byte()?.unwrap_or(EOF_VALUE)
+                };
+            }
+            SimOperation::Clear => {
+                self.tape[self.tape_pos] = 0;
+            }
+            SimOperation::AddToTheRightAndClear(offset) => {
+                let target = self.right_of(offset)?;
+                self.tape[target] = self.tape[target].wrapping_add(self.tape[self.tape_pos]) & self.cell_mask;
+                self.tape[self.tape_pos] = 0;
+            }
+            SimOperation::DecFromTheRightAndClear(offset) => {
+                let target = self.right_of(offset)?;
+                self.tape[target] = self.tape[target].wrapping_sub(self.tape[self.tape_pos]) & self.cell_mask;
+                self.tape[self.tape_pos] = 0
+            }
+            SimOperation::AddToTheLeftAndClear(offset) => {
+                if self.tape[self.tape_pos] != 0 {
+                    let target = self.left_of(offset)?;
+                    self.tape[target] = self.tape[target].wrapping_add(self.tape[self.tape_pos]) & self.cell_mask;
+                    self.tape[self.tape_pos] = 0;
+                }
+            }
+            SimOperation::DecFromTheLeftAndClear(offset) => {
+                if self.tape[self.tape_pos] != 0 {
+                    let target = self.left_of(offset)?;
+                    self.tape[target] = self.tape[target].wrapping_sub(self.tape[self.tape_pos]) & self.cell_mask;
+                    self.tape[self.tape_pos] = 0;
+                }
+            }
+            SimOperation::Debug => {
+                // Flushed first so the dump shows up after the output preceding it.
+                self.output.flush()?;
+                eprintln!("{}", dump_tape(&self.tape, self.tape_pos));
+            }
+            SimOperation::JnzSaveIP { target_ip } => {
+                if self.tape[self.tape_pos] != 0 {
+                    self.ip_stack.push(self.instruction_pointer + 1);
+                    self.instruction_pointer = target_ip as usize;
+                    return Ok(Status::Running);
+                }
+            }
+            SimOperation::JnzRestoreIP { target_ip } => {
+                if self.tape[self.tape_pos] != 0 {
+                    self.instruction_pointer = target_ip as usize;
+                } else {
+                    match self.ip_stack.pop() {
+                        None => return Ok(Status::Finished),
+                        Some(ip) => {
+                            self.instruction_pointer = ip;
+                        }
+                    }
+                }
+                return Ok(Status::Running);
+            }
+            SimOperation::DefineProcedure { target_ip } => {
+                self.procedures[self.tape[self.tape_pos] as usize] = Some(target_ip as usize);
+            }
+            SimOperation::CallProcedure => {
+                let procedure = self.tape[self.tape_pos];
+                let Some(target_ip) = self.procedures[procedure as usize] else {
+                    return Err(Error::UndefinedProcedure { procedure, instruction_pointer: self.instruction_pointer });
+                };
+                if self.call_stack.len() == MAX_CALL_DEPTH {
+                    return Err(Error::CallStackOverflow { instruction_pointer: self.instruction_pointer });
+                }
+                self.call_stack.push(self.instruction_pointer + 1);
+                self.instruction_pointer = target_ip;
+                return Ok(Status::Running);
+            }
+            SimOperation::Return => {
+                self.instruction_pointer = self.call_stack.pop().expect("procedures return where they were called");
+                return Ok(Status::Running);
+            }
+            SimOperation::Fork if !THREADS => unreachable!("`run` schedules forking programs"),
+            SimOperation::Fork => {
+                let tape_pos = self.right_of(1)?;
+                self.tape[self.tape_pos] = 0;
+                self.tape[tape_pos] = 1;
+                if self.scheduler.waiting.is_empty() {
+                    self.scheduler.slice_left = self.scheduler.next_slice();
+                }
+                self.scheduler.forked += 1;
+                self.scheduler.waiting.push_back(Thread {
+                    id: self.scheduler.forked,
+                    tape_pos,
+                    instruction_pointer: self.instruction_pointer + 1,
+                    ip_stack: self.ip_stack.clone(),
+                    call_stack: self.call_stack.clone()
+                });
+            }
+            SimOperation::End => {
+                if THREADS {
+                    self.scheduler.waiting.clear();
+                }
+                return Ok(Status::Finished);
+            }
+            SimOperation::Store => {
+                self.storage = self.tape[self.tape_pos];
+            }
+            SimOperation::Retrieve => {
+                self.tape[self.tape_pos] = self.storage & self.cell_mask;
+            }
+            SimOperation::ShiftLeft => {
+                self.tape[self.tape_pos] = (self.tape[self.tape_pos] << 1) & self.cell_mask;
+            }
+            SimOperation::ShiftRight => {
+                self.tape[self.tape_pos] >>= 1;
+            }
+            SimOperation::Not => {
+                self.tape[self.tape_pos] = !self.tape[self.tape_pos] & self.cell_mask;
+            }
+            SimOperation::Xor => {
+                self.tape[self.tape_pos] = (self.tape[self.tape_pos] ^ self.storage) & self.cell_mask;
+            }
+            SimOperation::And => {
+                self.tape[self.tape_pos] &= self.storage;
+            }
+            SimOperation::Or => {
+                self.tape[self.tape_pos] = (self.tape[self.tape_pos] | self.storage) & self.cell_mask;
+            }
+            SimOperation::Noop => {}
+            SimOperation::EndProgram => {
+                return Ok(Status::Finished);
+            }
+        }
+        self.instruction_pointer += 1;
+        Ok(Status::Running)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Error, Extensions, MAX_CALL_DEPTH, Machine, Status};
+    use super::dump_tape;
+
+    fn run_with_input(code: &str, input: &[u8]) -> Vec<u8> {
+        let mut machine = Machine::parse(code, Config::default(), input, Vec::new()).unwrap();
+        machine.run().unwrap();
+        machine.into_output()
+    }
+
+    #[test]
+    fn hello_world() {
+        let code = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+        assert_eq!(b"Hello World!\n".to_vec(), run_with_input(code, b""));
+    }
+
+    #[test]
+    fn input_is_echoed_until_eof() {
+        assert_eq!(b"abc".to_vec(), run_with_input(",+[-.,+]", b"abc"));
+    }
+
+    #[test]
+    fn step_reports_finish() {
+        let config = Config { tape_size: 1, ..Config::default() };
+        let mut machine = Machine::parse("+.", config, &b""[..], Vec::new()).unwrap();
+        assert_eq!(Status::Running, machine.step().unwrap());
+        assert_eq!(Status::Running, machine.step().unwrap());
+        assert_eq!(Status::Finished, machine.step().unwrap());
+        assert_eq!(&[1], machine.tape());
+        assert_eq!(vec![1], machine.into_output());
+    }
+
+    #[test]
+    fn dump_shows_cells_around_the_position() {
+        let tape: Vec<u8> = (0..32).collect();
+        assert_eq!("tape_pos 2, cells 0..11: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]", dump_tape(&tape, 2));
+        assert_eq!("tape_pos 20, cells 12..29: [12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28]", dump_tape(&tape, 20));
+        assert_eq!("tape_pos 30, cells 22..32: [22, 23, 24, 25, 26, 27, 28, 29, 30, 31]", dump_tape(&tape, 30));
+    }
+
+    #[test]
+    fn tape_bounds_are_checked() {
+        let config = Config { tape_size: 4, ..Config::default() };
+        let mut machine = Machine::parse("+[>+]", config, &b""[..], Vec::new()).unwrap();
+        assert!(matches!(machine.run(), Err(Error::TapeOverflow { .. })));
+        assert_eq!(3, machine.tape_pos());
+
+        let mut machine = Machine::parse("><<", Config::default(), &b""[..], Vec::new()).unwrap();
+        assert!(matches!(machine.run(), Err(Error::TapeUnderflow { instruction_pointer: 1 })));
+
+        assert!(matches!(
+            Machine::parse("[", Config::default(), &b""[..], Vec::new()),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn procedures_are_called_through_the_call_stack() {
+        let config = Config { extensions: Extensions { procedures: true, ..Extensions::default() }, ..Config::default() };
+        let code = "(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:";
+        let mut machine = Machine::parse(code, config, &b""[..], Vec::new()).unwrap();
+        machine.run().unwrap();
+        assert_eq!(0, machine.tape_pos());
+        assert!(machine.call_stack().is_empty());
+        assert_eq!(vec![3, 2, 1, b'A'], machine.into_output());
+
+        let mut machine = Machine::parse("(+):+:", config, &b""[..], Vec::new()).unwrap();
+        assert!(matches!(machine.run(), Err(Error::UndefinedProcedure { procedure: 2, .. })));
+
+        let mut machine = Machine::parse("(:):", config, &b""[..], Vec::new()).unwrap();
+        assert!(matches!(machine.run(), Err(Error::CallStackOverflow { .. })));
+        assert_eq!(MAX_CALL_DEPTH, machine.call_stack().len());
+    }
+
+    #[test]
+    fn threads_take_turns() {
+        let run = |code: &str, time_slice: usize, schedule_seed: u64| {
+            let extensions = Extensions { fork: true, ..Extensions::default() };
+            let config = Config { tape_size: 4, extensions, time_slice, schedule_seed, ..Config::default() };
+            let mut machine = Machine::parse(code, config, &b""[..], Vec::new()).unwrap();
+            machine.run().map(|_| machine.into_output())
+        };
+        // The parent counts from 0 and the child from 1, on their own cells.
+        assert_eq!(vec![2, 1, 3, 2, 4, 3], run("Y+.+.+.", 1, 0).unwrap());
+        assert_eq!(vec![1, 2, 3, 2, 3, 4], run("Y+.+.+.", 100, 0).unwrap());
+        // Threads share the tape: the child waits for the parent to clear the third cell,
+        // after setting the first one which the child then prints.
+        for time_slice in [1, 2, 5] {
+            assert_eq!(vec![7], run(">>+<<Y[->[><]<<.>]+++++++>>-", time_slice, 0).unwrap());
+        }
+
+        let schedules: Vec<_> = (1..8).map(|seed| run("Y+.+.+.", 3, seed).unwrap()).collect();
+        assert_eq!(schedules, (1..8).map(|seed| run("Y+.+.+.", 3, seed).unwrap()).collect::<Vec<_>>());
+        assert!(schedules.iter().any(|output| *output != schedules[0]), "{schedules:?}");
+
+        assert!(matches!(run(">>>Y", 1, 0), Err(Error::TapeOverflow { instruction_pointer: 1 })));
+    }
+
+    #[test]
+    fn end_stops_every_thread() {
+        let run = |time_slice: usize| {
+            let extensions = Extensions { fork: true, extended: true, ..Extensions::default() };
+            let config = Config { extensions, time_slice, ..Config::default() };
+            let mut machine = Machine::parse("Y[@]+.+.", config, &b""[..], Vec::new()).unwrap();
+            machine.run().unwrap();
+            assert_eq!(1, machine.threads());
+            machine.into_output()
+        };
+        // The child ends the program as soon as it runs, or once the parent is done.
+        assert_eq!(Vec::<u8>::new(), run(1));
+        assert_eq!(vec![1, 2], run(100));
+    }
+
+    #[test]
+    fn boolfuck_reads_and_writes_bits() {
+        let run = |code: &str, input: &[u8]| {
+            let config = Config { boolfuck: true, ..Config::default() };
+            let mut machine = Machine::parse(code, config, input, Vec::new()).unwrap();
+            machine.run().unwrap();
+            machine.into_output()
+        };
+        // Flips the cell whenever the next bit differs, least significant bit first.
+        let mut hello = String::new();
+        let mut cell = 0;
+        for bit in b"Hello World!\n".iter().flat_map(|byte| (0..8).map(move |i| byte >> i & 1)) {
+            if bit != cell {
+                hello.push('+');
+                cell = bit;
+            }
+            hello.push(';');
+        }
+        assert_eq!(b"Hello World!\n".to_vec(), run(&hello, b""));
+        assert_eq!(b"Hi".to_vec(), run(&",;".repeat(16), b"Hi"));
+        // Cells wrap at 2, a partial byte is padded with zeros and input ends with zeros.
+        assert_eq!(vec![0b011], run("+;++;+;", b""));
+        assert_eq!(vec![0], run(",;", b""));
+    }
+}
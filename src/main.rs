@@ -1,301 +1,270 @@
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
 use std::time::Instant;
+use brain_fuck_interpreter::{
+    Config, Dialect, Error, FormatOptions, format, minify, prepare, split_inline_input, try_parse_bf_source, try_parse_bf_with
+};
+use brain_fuck_interpreter::emit::{Emit, emit, emit_rust};
 
 #[cfg(feature = "use_codegen")]
 mod codegen {
-    use proc_macro_bf::bf;
-    bf!{run_mandelbrot_generated, r##"
-+++++++++++++[->++>>>+++++>++>+<<<<<<]>>>>>++++++>--->>>>>>>>>>+++++++++++++++[[
->>>>>>>>>]+[<<<<<<<<<]>>>>>>>>>-]+[>>>>>>>>[-]>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>[-]+
-<<<<<<<+++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>>>+>>>>>>>>>>>>>>>>>>>>>>>>>>
->+<<<<<<<<<<<<<<<<<[<<<<<<<<<]>>>[-]+[>>>>>>[>>>>>>>[-]>>]<<<<<<<<<[<<<<<<<<<]>>
->>>>>[-]+<<<<<<++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>>+<<<<<<+++++++[-[->>>
->>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>>+<<<<<<<<<<<<<<<<[<<<<<<<<<]>>>[[-]>>>>>>[>>>>>
->>[-<<<<<<+>>>>>>]<<<<<<[->>>>>>+<<+<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>
-[>>>>>>>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+<<+<<<+<<]>>>>>>>>]<<<<<<<<<[<<<<<<<
-<<]>>>>>>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+<<+<<<<<]>>>>>>>>>+++++++++++++++[[
->>>>>>>>>]+>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+[
->+>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>[-<<<<+>>>>]<<<<[->>>>+<<<<<[->>[
--<<+>>]<<[->>+>>+<<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>>>>]<<<<<<<
-<<[>[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<]>[->>>>>>>>>+<<<<<<<<<]<+>>>>>>>>]<<<<<<<<<
-[>[-]<->>>>[-<<<<+>[<->-<<<<<<+>>>>>>]<[->+<]>>>>]<<<[->>>+<<<]<+<<<<<<<<<]>>>>>
->>>>[>+>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>>[-<<<<<+>>>>>]<<<<<[->>>>>+
-<<<<<<[->>>[-<<<+>>>]<<<[->>>+>+<<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>
->>>>>>>]<<<<<<<<<[>>[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<<]>>[->>>>>>>>>+<<<<<<<<<]<<
-+>>>>>>>>]<<<<<<<<<[>[-]<->>>>[-<<<<+>[<->-<<<<<<+>>>>>>]<[->+<]>>>>]<<<[->>>+<<
-<]<+<<<<<<<<<]>>>>>>>>>[>>>>[-<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>>
->>>>>>>>>>>>>>>>>>>>>>>]>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>+++++++++++++++[[>>>>
->>>>>]<<<<<<<<<-<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+>>>>>>>>>>>>>>>>>>>>>+<<<[<<<<<<
-<<<]>>>>>>>>>[>>>[-<<<->>>]+<<<[->>>->[-<<<<+>>>>]<<<<[->>>>+<<<<<<<<<<<<<[<<<<<
-<<<<]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>>>[-<<<<->>>>]+<<<<[->>>>-<[-<<<+>>>]<<<[->
->>+<<<<<<<<<<<<[<<<<<<<<<]>>>[-]+>>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>>>>>>>>]<<<<<<
-<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]<<<<<<<[->+>>>-<<<<]>>>>>>>>>+++++++++++++++++++
-+++++++>>[-<<<<+>>>>]<<<<[->>>>+<<[-]<<]>>[<<<<<<<+<[-<+>>>>+<<[-]]>[-<<[->+>>>-
-<<<<]>>>]>>>>>>>>>>>>>[>>[-]>[-]>[-]>>>>>]<<<<<<<<<[<<<<<<<<<]>>>[-]>>>>>>[>>>>>
-[-<<<<+>>>>]<<<<[->>>>+<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>[-<<<<<<<<
-<+>>>>>>>>>]>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>+++++++++++++++[[>>>>>>>>>]+>[-
-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+[>+>>>>>>>>]<<<
-<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>>[-<<<<<+>>>>>]<<<<<[->>>>>+<<<<<<[->>[-<<+>>]<
-<[->>+>+<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>>>>]<<<<<<<<<[>[->>>>
->>>>>+<<<<<<<<<]<<<<<<<<<<]>[->>>>>>>>>+<<<<<<<<<]<+>>>>>>>>]<<<<<<<<<[>[-]<->>>
-[-<<<+>[<->-<<<<<<<+>>>>>>>]<[->+<]>>>]<<[->>+<<]<+<<<<<<<<<]>>>>>>>>>[>>>>>>[-<
-<<<<+>>>>>]<<<<<[->>>>>+<<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>+>>>>>>>>
-]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>>[-<<<<<+>>>>>]<<<<<[->>>>>+<<<<<<[->>[-<<+
->>]<<[->>+>>+<<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>>>>]<<<<<<<<<[>
-[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<]>[->>>>>>>>>+<<<<<<<<<]<+>>>>>>>>]<<<<<<<<<[>[-
-]<->>>>[-<<<<+>[<->-<<<<<<+>>>>>>]<[->+<]>>>>]<<<[->>>+<<<]<+<<<<<<<<<]>>>>>>>>>
-[>>>>[-<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>
-]>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>[-<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<+>
->>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>]>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>++++++++
-+++++++[[>>>>>>>>>]<<<<<<<<<-<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+[>>>>>>>>[-<<<<<<<+
->>>>>>>]<<<<<<<[->>>>>>>+<<<<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>>>>[
--]>>>]<<<<<<<<<[<<<<<<<<<]>>>>+>[-<-<<<<+>>>>>]>[-<<<<<<[->>>>>+<++<<<<]>>>>>[-<
-<<<<+>>>>>]<->+>]<[->+<]<<<<<[->>>>>+<<<<<]>>>>>>[-]<<<<<<+>>>>[-<<<<->>>>]+<<<<
-[->>>>->>>>>[>>[-<<->>]+<<[->>->[-<<<+>>>]<<<[->>>+<<<<<<<<<<<<[<<<<<<<<<]>>>[-]
-+>>>>>>[>>>>>>>>>]>+<]]+>>>[-<<<->>>]+<<<[->>>-<[-<<+>>]<<[->>+<<<<<<<<<<<[<<<<<
-<<<<]>>>>[-]+>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>>>>>>>>]<<<<<<<<]>>>>>>>>]<<<<<<<<<
-[<<<<<<<<<]>>>>[-<<<<+>>>>]<<<<[->>>>+>>>>>[>+>>[-<<->>]<<[->>+<<]>>>>>>>>]<<<<<
-<<<+<[>[->>>>>+<<<<[->>>>-<<<<<<<<<<<<<<+>>>>>>>>>>>[->>>+<<<]<]>[->>>-<<<<<<<<<
-<<<<<+>>>>>>>>>>>]<<]>[->>>>+<<<[->>>-<<<<<<<<<<<<<<+>>>>>>>>>>>]<]>[->>>+<<<]<<
-<<<<<<<<<<]>>>>[-]<<<<]>>>[-<<<+>>>]<<<[->>>+>>>>>>[>+>[-<->]<[->+<]>>>>>>>>]<<<
-<<<<<+<[>[->>>>>+<<<[->>>-<<<<<<<<<<<<<<+>>>>>>>>>>[->>>>+<<<<]>]<[->>>>-<<<<<<<
-<<<<<<<+>>>>>>>>>>]<]>>[->>>+<<<<[->>>>-<<<<<<<<<<<<<<+>>>>>>>>>>]>]<[->>>>+<<<<
-]<<<<<<<<<<<]>>>>>>+<<<<<<]]>>>>[-<<<<+>>>>]<<<<[->>>>+>>>>>[>>>>>>>>>]<<<<<<<<<
-[>[->>>>>+<<<<[->>>>-<<<<<<<<<<<<<<+>>>>>>>>>>>[->>>+<<<]<]>[->>>-<<<<<<<<<<<<<<
-+>>>>>>>>>>>]<<]>[->>>>+<<<[->>>-<<<<<<<<<<<<<<+>>>>>>>>>>>]<]>[->>>+<<<]<<<<<<<
-<<<<<]]>[-]>>[-]>[-]>>>>>[>>[-]>[-]>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>>>[-<
-<<<+>>>>]<<<<[->>>>+<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>+++++++++++++++[
-[>>>>>>>>>]+>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+
-[>+>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>[-<<<<+>>>>]<<<<[->>>>+<<<<<[->>
-[-<<+>>]<<[->>+>+<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>>>>]<<<<<<<<
-<[>[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<]>[->>>>>>>>>+<<<<<<<<<]<+>>>>>>>>]<<<<<<<<<[
->[-]<->>>[-<<<+>[<->-<<<<<<<+>>>>>>>]<[->+<]>>>]<<[->>+<<]<+<<<<<<<<<]>>>>>>>>>[
->>>[-<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>]>
->>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>[-]>>>>+++++++++++++++[[>>>>>>>>>]<<<<<<<<<-<<<<<
-<<<<[<<<<<<<<<]>>>>>>>>>-]+[>>>[-<<<->>>]+<<<[->>>->[-<<<<+>>>>]<<<<[->>>>+<<<<<
-<<<<<<<<[<<<<<<<<<]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>>>[-<<<<->>>>]+<<<<[->>>>-<[-
-<<<+>>>]<<<[->>>+<<<<<<<<<<<<[<<<<<<<<<]>>>[-]+>>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>
->>>>>>>]<<<<<<<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>[-<<<+>>>]<<<[->>>+>>>>>>[>+>>>
-[-<<<->>>]<<<[->>>+<<<]>>>>>>>>]<<<<<<<<+<[>[->+>[-<-<<<<<<<<<<+>>>>>>>>>>>>[-<<
-+>>]<]>[-<<-<<<<<<<<<<+>>>>>>>>>>>>]<<<]>>[-<+>>[-<<-<<<<<<<<<<+>>>>>>>>>>>>]<]>
-[-<<+>>]<<<<<<<<<<<<<]]>>>>[-<<<<+>>>>]<<<<[->>>>+>>>>>[>+>>[-<<->>]<<[->>+<<]>>
->>>>>>]<<<<<<<<+<[>[->+>>[-<<-<<<<<<<<<<+>>>>>>>>>>>[-<+>]>]<[-<-<<<<<<<<<<+>>>>
->>>>>>>]<<]>>>[-<<+>[-<-<<<<<<<<<<+>>>>>>>>>>>]>]<[-<+>]<<<<<<<<<<<<]>>>>>+<<<<<
-]>>>>>>>>>[>>>[-]>[-]>[-]>>>>]<<<<<<<<<[<<<<<<<<<]>>>[-]>[-]>>>>>[>>>>>>>[-<<<<<
-<+>>>>>>]<<<<<<[->>>>>>+<<<<+<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>+>[-<-<<<<+>>>>
->]>>[-<<<<<<<[->>>>>+<++<<<<]>>>>>[-<<<<<+>>>>>]<->+>>]<<[->>+<<]<<<<<[->>>>>+<<
-<<<]+>>>>[-<<<<->>>>]+<<<<[->>>>->>>>>[>>>[-<<<->>>]+<<<[->>>-<[-<<+>>]<<[->>+<<
-<<<<<<<<<[<<<<<<<<<]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>[-<<->>]+<<[->>->[-<<<+>>>]<
-<<[->>>+<<<<<<<<<<<<[<<<<<<<<<]>>>[-]+>>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>>>>>>>>]<
-<<<<<<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>[-<<<+>>>]<<<[->>>+>>>>>>[>+>[-<->]<[->+
-<]>>>>>>>>]<<<<<<<<+<[>[->>>>+<<[->>-<<<<<<<<<<<<<+>>>>>>>>>>[->>>+<<<]>]<[->>>-
-<<<<<<<<<<<<<+>>>>>>>>>>]<]>>[->>+<<<[->>>-<<<<<<<<<<<<<+>>>>>>>>>>]>]<[->>>+<<<
-]<<<<<<<<<<<]>>>>>[-]>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+<<+<<<<<]]>>>>[-<<<<+>
->>>]<<<<[->>>>+>>>>>[>+>>[-<<->>]<<[->>+<<]>>>>>>>>]<<<<<<<<+<[>[->>>>+<<<[->>>-
-<<<<<<<<<<<<<+>>>>>>>>>>>[->>+<<]<]>[->>-<<<<<<<<<<<<<+>>>>>>>>>>>]<<]>[->>>+<<[
-->>-<<<<<<<<<<<<<+>>>>>>>>>>>]<]>[->>+<<]<<<<<<<<<<<<]]>>>>[-]<<<<]>>>>[-<<<<+>>
->>]<<<<[->>>>+>[-]>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+<<+<<<<<]>>>>>>>>>[>>>>>>
->>>]<<<<<<<<<[>[->>>>+<<<[->>>-<<<<<<<<<<<<<+>>>>>>>>>>>[->>+<<]<]>[->>-<<<<<<<<
-<<<<<+>>>>>>>>>>>]<<]>[->>>+<<[->>-<<<<<<<<<<<<<+>>>>>>>>>>>]<]>[->>+<<]<<<<<<<<
-<<<<]]>>>>>>>>>[>>[-]>[-]>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>[-]>[-]>>>>>[>>>>>[-<<<<+
->>>>]<<<<[->>>>+<<<+<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>>>>[-<<<<<+>>>>>
-]<<<<<[->>>>>+<<<+<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>+++++++++++++++[[>>>>
->>>>>]+>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]>[-]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+[>+>>
->>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>[-<<<<+>>>>]<<<<[->>>>+<<<<<[->>[-<<+
->>]<<[->>+>>+<<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>>>>]<<<<<<<<<[>
-[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<]>[->>>>>>>>>+<<<<<<<<<]<+>>>>>>>>]<<<<<<<<<[>[-
-]<->>>>[-<<<<+>[<->-<<<<<<+>>>>>>]<[->+<]>>>>]<<<[->>>+<<<]<+<<<<<<<<<]>>>>>>>>>
-[>+>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>->>>>>[-<<<<<+>>>>>]<<<<<[->>>>>+<<<<
-<<[->>>[-<<<+>>>]<<<[->>>+>+<<<<]+>>>>>>>>>]<<<<<<<<[<<<<<<<<<]]>>>>>>>>>[>>>>>>
->>>]<<<<<<<<<[>>[->>>>>>>>>+<<<<<<<<<]<<<<<<<<<<<]>>[->>>>>>>>>+<<<<<<<<<]<<+>>>
->>>>>]<<<<<<<<<[>[-]<->>>>[-<<<<+>[<->-<<<<<<+>>>>>>]<[->+<]>>>>]<<<[->>>+<<<]<+
-<<<<<<<<<]>>>>>>>>>[>>>>[-<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<+>>>>>>>>>>>>>>>>>
->>>>>>>>>>>>>>>>>>>]>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>+++++++++++++++[[>>>>>>>>
->]<<<<<<<<<-<<<<<<<<<[<<<<<<<<<]>>>>>>>>>-]+>>>>>>>>>>>>>>>>>>>>>+<<<[<<<<<<<<<]
->>>>>>>>>[>>>[-<<<->>>]+<<<[->>>->[-<<<<+>>>>]<<<<[->>>>+<<<<<<<<<<<<<[<<<<<<<<<
-]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>>>[-<<<<->>>>]+<<<<[->>>>-<[-<<<+>>>]<<<[->>>+<
-<<<<<<<<<<<[<<<<<<<<<]>>>[-]+>>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>>>>>>>>]<<<<<<<<]>
->>>>>>>]<<<<<<<<<[<<<<<<<<<]>>->>[-<<<<+>>>>]<<<<[->>>>+<<[-]<<]>>]<<+>>>>[-<<<<
-->>>>]+<<<<[->>>>-<<<<<<.>>]>>>>[-<<<<<<<.>>>>>>>]<<<[-]>[-]>[-]>[-]>[-]>[-]>>>[
->[-]>[-]>[-]>[-]>[-]>[-]>>>]<<<<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>>>[-]>>>>]<<<<<<<<<
-[<<<<<<<<<]>+++++++++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>+>>>>>>>>>+<<<<<<<<
-<<<<<<[<<<<<<<<<]>>>>>>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+[-]>>[>>>>>>>>>]<<<<<
-<<<<[>>>>>>>[-<<<<<<+>>>>>>]<<<<<<[->>>>>>+<<<<<<<[<<<<<<<<<]>>>>>>>[-]+>>>]<<<<
-<<<<<<]]>>>>>>>[-<<<<<<<+>>>>>>>]<<<<<<<[->>>>>>>+>>[>+>>>>[-<<<<->>>>]<<<<[->>>
->+<<<<]>>>>>>>>]<<+<<<<<<<[>>>>>[->>+<<]<<<<<<<<<<<<<<]>>>>>>>>>[>>>>>>>>>]<<<<<
-<<<<[>[-]<->>>>>>>[-<<<<<<<+>[<->-<<<+>>>]<[->+<]>>>>>>>]<<<<<<[->>>>>>+<<<<<<]<
-+<<<<<<<<<]>>>>>>>-<<<<[-]+<<<]+>>>>>>>[-<<<<<<<->>>>>>>]+<<<<<<<[->>>>>>>->>[>>
->>>[->>+<<]>>>>]<<<<<<<<<[>[-]<->>>>>>>[-<<<<<<<+>[<->-<<<+>>>]<[->+<]>>>>>>>]<<
-<<<<[->>>>>>+<<<<<<]<+<<<<<<<<<]>+++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>+<<<
-<<[<<<<<<<<<]>>>>>>>>>[>>>>>[-<<<<<->>>>>]+<<<<<[->>>>>->>[-<<<<<<<+>>>>>>>]<<<<
-<<<[->>>>>>>+<<<<<<<<<<<<<<<<[<<<<<<<<<]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>>>>>>[-<
-<<<<<<->>>>>>>]+<<<<<<<[->>>>>>>-<<[-<<<<<+>>>>>]<<<<<[->>>>>+<<<<<<<<<<<<<<[<<<
-<<<<<<]>>>[-]+>>>>>>[>>>>>>>>>]>[-]+<]]+>[-<[>>>>>>>>>]<<<<<<<<]>>>>>>>>]<<<<<<<
-<<[<<<<<<<<<]>>>>[-]<<<+++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>-<<<<<[<<<<<<<
-<<]]>>>]<<<<.>>>>>>>>>>[>>>>>>[-]>>>]<<<<<<<<<[<<<<<<<<<]>++++++++++[-[->>>>>>>>
->+<<<<<<<<<]>>>>>>>>>]>>>>>+>>>>>>>>>+<<<<<<<<<<<<<<<[<<<<<<<<<]>>>>>>>>[-<<<<<<
-<<+>>>>>>>>]<<<<<<<<[->>>>>>>>+[-]>[>>>>>>>>>]<<<<<<<<<[>>>>>>>>[-<<<<<<<+>>>>>>
->]<<<<<<<[->>>>>>>+<<<<<<<<[<<<<<<<<<]>>>>>>>>[-]+>>]<<<<<<<<<<]]>>>>>>>>[-<<<<<
-<<<+>>>>>>>>]<<<<<<<<[->>>>>>>>+>[>+>>>>>[-<<<<<->>>>>]<<<<<[->>>>>+<<<<<]>>>>>>
->>]<+<<<<<<<<[>>>>>>[->>+<<]<<<<<<<<<<<<<<<]>>>>>>>>>[>>>>>>>>>]<<<<<<<<<[>[-]<-
->>>>>>>>[-<<<<<<<<+>[<->-<<+>>]<[->+<]>>>>>>>>]<<<<<<<[->>>>>>>+<<<<<<<]<+<<<<<<
-<<<]>>>>>>>>-<<<<<[-]+<<<]+>>>>>>>>[-<<<<<<<<->>>>>>>>]+<<<<<<<<[->>>>>>>>->[>>>
->>>[->>+<<]>>>]<<<<<<<<<[>[-]<->>>>>>>>[-<<<<<<<<+>[<->-<<+>>]<[->+<]>>>>>>>>]<<
-<<<<<[->>>>>>>+<<<<<<<]<+<<<<<<<<<]>+++++[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>
-+>>>>>>>>>>>>>>>>>>>>>>>>>>>+<<<<<<[<<<<<<<<<]>>>>>>>>>[>>>>>>[-<<<<<<->>>>>>]+<
-<<<<<[->>>>>>->>[-<<<<<<<<+>>>>>>>>]<<<<<<<<[->>>>>>>>+<<<<<<<<<<<<<<<<<[<<<<<<<
-<<]>>>>[-]+>>>>>[>>>>>>>>>]>+<]]+>>>>>>>>[-<<<<<<<<->>>>>>>>]+<<<<<<<<[->>>>>>>>
--<<[-<<<<<<+>>>>>>]<<<<<<[->>>>>>+<<<<<<<<<<<<<<<[<<<<<<<<<]>>>[-]+>>>>>>[>>>>>>
->>>]>[-]+<]]+>[-<[>>>>>>>>>]<<<<<<<<]>>>>>>>>]<<<<<<<<<[<<<<<<<<<]>>>>[-]<<<++++
-+[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>->>>>>>>>>>>>>>>>>>>>>>>>>>>-<<<<<<[<<<<
-<<<<<]]>>>]
-"##}
+    use proc_macro_bf::include_bf;
+    include_bf!{run_mandelbrot_generated, "src/mandelbrot.b", unsafe, padding = 16}
 }
 
 #[cfg(not(feature = "use_codegen"))]
-mod interpreter {
-    use std::collections::VecDeque;
-    use brain_fuck_parser::{parse_bf, SimOperation};
+const MANDELBROT: &str = include_str!("mandelbrot.b");
 
-    #[inline(always)]
-    fn get_char_impl() -> u8 {
-        let c = unsafe { libc::getchar() };
-        c as u8
+const USAGE: &str = "\
+usage: bf [run] [options] <file.b>          run the program
+       bf debug [options] <file.b>          debug the program with reverse stepping
+       bf compile --emit <format> <file.b>  compile the program ahead of time
+       bf fmt [options] <file.b>            print the program formatted
+       bf minify [options] <file.b>         print the shortest equivalent program
+       bf [options]                         run the built-in mandelbrot
+
+options:
+  --backend <switch|threaded|jit>           execution engine, switch by default
+  --extensions <debug,pbrain,brainfork,ebf> comma separated language extensions: debug
+                                            makes `#` dump the tape to stderr, pbrain
+                                            adds `(`, `)` and `:` procedures, brainfork
+                                            adds `Y` forking a thread, ebf adds Extended
+                                            Brainfuck Type I's `@$!{}~^&|`
+  --time-slice <steps>                      steps a thread runs before the next one, 1
+                                            by default
+  --schedule-seed <seed>                    draw time slices of 1 to --time-slice steps
+                                            with that seed rather than full ones
+  --dialect <ook|blub|file.toml|file.json>  language the program is written in, BF by
+                                            default; files map every command to a token
+  --boolfuck                                run the program as Boolfuck, on bits rather
+                                            than bytes
+  --input <path>                            file the program reads, stdin by default
+  --inline-input                            read the program's input from after the first
+                                            `!` of the file, unless --input is given
+  --evaluate-prefix <steps>                 evaluate up to that many steps of what runs
+                                            before the first input ahead of time
+  --emit <asm|c|llvm|rust|wasm|wat>         output format of compile
+  -o <path>                                 output file of compile, fmt and minify,
+                                            stdout by default; the crate directory for
+                                            rust
+  --width <columns>                         line width of fmt, 80 by default
+  --keep-comments                           keep the comments of the program in fmt";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    std::process::exit(2)
+}
+
+#[derive(Default)]
+struct Options {
+    positional: Vec<String>,
+    config: Config,
+    dialect: Option<Dialect>,
+    input: Option<String>,
+    inline_input: bool,
+    emit: Option<Emit>,
+    output: Option<String>,
+    format: FormatOptions
+}
+
+fn value_of(option: &str, args: &mut impl Iterator<Item = String>) -> String {
+    args.next().unwrap_or_else(|| usage_error(&format!("{option} expects a value")))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Options {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--backend" => {
+                let value = value_of("--backend", &mut args);
+                options.config.backend = value.parse().unwrap_or_else(|err: String| usage_error(&err));
+            }
+            "--extensions" => {
+                let value = value_of("--extensions", &mut args);
+                options.config.extensions = value.parse().unwrap_or_else(|err: String| usage_error(&err));
+            }
+            "--dialect" => {
+                let value = value_of("--dialect", &mut args);
+                let dialect = Dialect::named(&value).map_or_else(|| Dialect::load(Path::new(&value)), Ok);
+                options.dialect = Some(dialect.unwrap_or_else(|err| usage_error(&format!("--dialect {value}: {err}"))));
+            }
+            "--evaluate-prefix" => {
+                let value = value_of("--evaluate-prefix", &mut args);
+                options.config.evaluate_prefix = value.parse().unwrap_or_else(|_| {
+                    usage_error(&format!("--evaluate-prefix expects a number of steps, got {value}"))
+                });
+            }
+            "--time-slice" => {
+                let value = value_of("--time-slice", &mut args);
+                options.config.time_slice = value.parse().ok().filter(|&steps| steps > 0).unwrap_or_else(|| {
+                    usage_error(&format!("--time-slice expects a positive number of steps, got {value}"))
+                });
+            }
+            "--schedule-seed" => {
+                let value = value_of("--schedule-seed", &mut args);
+                options.config.schedule_seed = value.parse().unwrap_or_else(|_| {
+                    usage_error(&format!("--schedule-seed expects a number, got {value}"))
+                });
+            }
+            "--emit" => {
+                let value = value_of("--emit", &mut args);
+                options.emit = Some(value.parse().unwrap_or_else(|err: String| usage_error(&err)));
+            }
+            "--boolfuck" => options.config.boolfuck = true,
+            "--input" => options.input = Some(value_of("--input", &mut args)),
+            "--inline-input" => options.inline_input = true,
+            "-o" => options.output = Some(value_of("-o", &mut args)),
+            "--width" => {
+                let value = value_of("--width", &mut args);
+                options.format.width = value.parse().ok().filter(|&columns| columns > 0).unwrap_or_else(|| {
+                    usage_error(&format!("--width expects a positive number of columns, got {value}"))
+                });
+            }
+            "--keep-comments" => options.format.comments = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                std::process::exit(0)
+            }
+            _ if arg.starts_with('-') => usage_error(&format!("unknown option {arg}")),
+            _ => options.positional.push(arg)
+        }
+    }
+    if options.config.boolfuck && options.dialect.is_some() {
+        usage_error("--boolfuck and --dialect are different languages");
+    }
+    if options.inline_input && options.config.extensions.extended {
+        usage_error("--inline-input and the ebf extension both use `!`");
     }
+    options
+}
 
-    const MANDELBROT: &str = include_str!("mandelbrot.b");
+/// Reads the program, translated to BF if it's written in a dialect, and with --inline-input
+/// the input following its first `!`.
+fn read_program(path: Option<&String>, options: &Options) -> Result<(String, Vec<u8>), Error> {
+    let path = path.unwrap_or_else(|| usage_error("expected a program file"));
+    let source = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("failed to read {path}: {err}");
+        std::process::exit(1)
+    });
+    let (source, input) = if options.inline_input { split_inline_input(&source) } else { (source.as_str(), &[][..]) };
+    let code = match &options.dialect {
+        Some(dialect) => dialect.translate(source)?,
+        None => source.to_string()
+    };
+    Ok((code, input.to_vec()))
+}
 
-    struct BfContext {
-        tape: Vec<u8>,
-        tape_pos: usize,
-        instruction_pointer: usize,
-        ip_stack: VecDeque<usize>
+/// What `,` reads: the --input file, else the inline input with --inline-input, else stdin.
+fn program_input(options: &Options, inline_input: Vec<u8>) -> Box<dyn Read> {
+    match &options.input {
+        Some(path) => Box::new(File::open(path).unwrap_or_else(|err| {
+            eprintln!("failed to read {path}: {err}");
+            std::process::exit(1)
+        })),
+        None if options.inline_input => Box::new(Cursor::new(inline_input)),
+        None => Box::new(std::io::stdin())
     }
+}
 
-    impl BfContext {
-        fn parse(input: &str) -> (Self, Vec<SimOperation>) {
-            let root_node = parse_bf(input);
-            (
-                Self {
-                    tape: vec![0; 0x100000],
-                    tape_pos: 0,
-                    instruction_pointer: 0,
-                    ip_stack: VecDeque::new()
-                },
-                root_node.compile_bytecode()
-            )
-        }
+fn run(path: Option<&String>, options: &Options) -> Result<(), Error> {
+    let (code, input) = read_program(path, options)?;
+    brain_fuck_interpreter::run_with_io(&code, options.config, program_input(options, input), std::io::stdout().lock())
+}
 
-        fn eval(&mut self, ops: &[SimOperation]) {
-            self.instruction_pointer = 0;
-            loop {
-                let node = *unsafe { ops.get_unchecked(self.instruction_pointer) };
-                match node {
-                    SimOperation::Inc(amount) => {
-                        self.tape[self.tape_pos] = self.tape[self.tape_pos].wrapping_add(amount) ;
-                    }
-                    SimOperation::Dec(amount) => {
-                        self.tape[self.tape_pos] = self.tape[self.tape_pos].wrapping_sub(amount);
-                    }
-                    SimOperation::IncTapePos(offset) => {
-                        self.tape_pos += offset as usize;
-                    }
-                    SimOperation::DecTapePos(offset) => {
-                        self.tape_pos -= offset as usize;
-                    }
-                    SimOperation::IncTapePosUntilEmpty => {
-                        while self.tape[self.tape_pos] != 0 { self.tape_pos += 1; }
-                    }
-                    SimOperation::DecTapePosUntilEmpty => {
-                        while self.tape[self.tape_pos] != 0 { self.tape_pos -= 1; }
-                    }
-                    SimOperation::PutChar => {
-                        print!("{}", self.tape[self.tape_pos] as char);
-                    }
-                    SimOperation::GetChar => {
-                        self.tape[self.tape_pos] = get_char_impl();
-                    }
-                    SimOperation::Clear => {
-                        self.tape[self.tape_pos] = 0;
-                    }
-                    SimOperation::AddToTheRightAndClear(offset) => {
-                        self.tape[self.tape_pos + offset as usize] =
-                            self.tape[self.tape_pos + offset as usize]
-                            .wrapping_add(self.tape[self.tape_pos]);
-                        self.tape[self.tape_pos] = 0;
-                    }
-                    SimOperation::DecFromTheRightAndClear(offset) => {
-                        self.tape[self.tape_pos + offset as usize] =
-                            self.tape[self.tape_pos + offset as usize]
-                            .wrapping_sub(self.tape[self.tape_pos]);
-                        self.tape[self.tape_pos] = 0
-                    }
-                    SimOperation::AddToTheLeftAndClear(offset) => {
-                        if self.tape[self.tape_pos] != 0 {
-                            self.tape[self.tape_pos - offset as usize] =
-                                self.tape[self.tape_pos - offset as usize]
-                                .wrapping_add(self.tape[self.tape_pos]);
-                            self.tape[self.tape_pos] = 0;
-                        }
-                    }
-                    SimOperation::DecFromTheLeftAndClear(offset) => {
-                        if self.tape[self.tape_pos] != 0 {
-                            self.tape[self.tape_pos - offset as usize] =
-                                self.tape[self.tape_pos - offset as usize]
-                                .wrapping_sub(self.tape[self.tape_pos]);
-                            self.tape[self.tape_pos] = 0;
-                        }
-                    }
-                    SimOperation::JnzSaveIP { target_ip } => {
-                        if self.tape[self.tape_pos] != 0 {
-                            self.ip_stack.push_back(self.instruction_pointer + 1);
-                            self.instruction_pointer = target_ip as usize;
-                            continue;
-                        }
-                    }
-                    SimOperation::JnzRestoreIP { target_ip } => {
-                        if self.tape[self.tape_pos] != 0 {
-                            self.instruction_pointer = target_ip as usize;
-                        } else {
-                            match self.ip_stack.pop_back() {
-                                None => return,
-                                Some(ip) => {
-                                    self.instruction_pointer = ip as usize;
-                                }
-                            }
-                        }
-                        continue;
-                    }
-                    SimOperation::Noop => {}
-                    SimOperation::EndProgram => {
-                        return;
-                    }
-                }
-                self.instruction_pointer += 1;
-            }
+fn compile(options: &Options) -> Result<(), Error> {
+    let format = options.emit.unwrap_or_else(|| usage_error("compile expects --emit"));
+    if options.config.boolfuck {
+        usage_error("Boolfuck only runs in the interpreter, the program can't be compiled");
+    }
+    if options.inline_input {
+        usage_error("compiled programs read stdin, --inline-input can't be compiled into them");
+    }
+    let path = options.positional.get(1);
+    let (code, _) = read_program(path, options)?;
+    let program = prepare(try_parse_bf_with(&code, options.config.extensions)?, &options.config);
+    match (format, &options.output) {
+        (Emit::Rust, Some(dir)) => {
+            let dir = Path::new(dir);
+            let name = Path::new(path.unwrap()).file_stem().and_then(|stem| stem.to_str()).unwrap_or("bf-program");
+            emit_rust(&program, &options.config, name)?.write_to(dir)?
         }
+        (_, Some(path)) => std::fs::write(path, emit(&program, format, &options.config)?)?,
+        (_, None) => std::io::stdout().write_all(&emit(&program, format, &options.config)?)?
     }
+    Ok(())
+}
 
-    pub fn run_mandelbrot() {
-        run(MANDELBROT);
+/// Writes the source of fmt or minify to -o or stdout, followed by the input of the program
+/// with --inline-input.
+fn write_source(options: &Options, source: String, input: Vec<u8>) -> Result<(), Error> {
+    let mut source = source.into_bytes();
+    if !input.is_empty() {
+        source.push(b'!');
+        source.extend(input);
+    }
+    match &options.output {
+        Some(path) => std::fs::write(path, source)?,
+        None => std::io::stdout().write_all(&source)?
     }
+    Ok(())
+}
 
-    fn run(code: &str) {
-        let (mut ctx, sim_ops) = BfContext::parse(code);
-        ctx.eval(&sim_ops);
+fn fmt(options: &Options) -> Result<(), Error> {
+    if options.config.boolfuck {
+        usage_error("fmt writes BF, it can't format Boolfuck");
     }
+    let (code, input) = read_program(options.positional.get(1), options)?;
+    let program = try_parse_bf_source(&code, options.config.extensions)?;
+    write_source(options, format(&program, &options.format), input)
 }
 
-fn main() {
-    let instant = Instant::now();
+fn minify_program(options: &Options) -> Result<(), Error> {
+    if options.config.boolfuck {
+        usage_error("minify writes BF, it can't minify Boolfuck");
+    }
+    let (code, input) = read_program(options.positional.get(1), options)?;
+    let program = try_parse_bf_with(&code, options.config.extensions)?;
+    write_source(options, minify(&program).to_string(), input)
+}
 
+fn run_mandelbrot(config: Config) -> Result<(), Error> {
     #[cfg(feature = "use_codegen")]
     {
+        let _ = config;
         codegen::run_mandelbrot_generated();
+        Ok(())
     }
     #[cfg(not(feature = "use_codegen"))]
     {
-        interpreter::run_mandelbrot();
+        brain_fuck_interpreter::run(MANDELBROT, config)
+    }
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1));
+    let args = &options.positional;
+    let config = options.config;
+
+    let result = match args.first().map(String::as_str) {
+        Some("debug") => read_program(args.get(1), &options).and_then(|(code, input)| {
+            brain_fuck_interpreter::debugger::debug_with_input(&code, config, program_input(&options, input))
+        }),
+        Some("run") => run(args.get(1), &options),
+        Some("compile") => compile(&options),
+        Some("fmt") => fmt(&options),
+        Some("minify") => minify_program(&options),
+        Some(_) => run(args.first(), &options),
+        None => {
+            let instant = Instant::now();
+            let result = run_mandelbrot(config);
+            let elapsed = instant.elapsed().as_secs_f32();
+            println!("time: {elapsed} seconds");
+            result
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("\n{err}");
+        std::process::exit(1);
     }
-    let elapsed = instant.elapsed().as_secs_f32();
-    println!("time: {elapsed} seconds");
 }
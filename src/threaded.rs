@@ -0,0 +1,372 @@
+use std::io::{Read, Write};
+use brain_fuck_parser::SimOperation;
+
+use crate::{Config, EOF_VALUE, Error, MAX_CALL_DEPTH};
+use crate::machine::dump_tape;
+
+struct Registers<'a> {
+    tape: Vec<u8>,
+    tape_pos: usize,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+    /// Bodies of the procedures of the program, and which of them every number stands for.
+    bodies: &'a [Box<[Handler]>],
+    procedures: [Option<usize>; 256],
+    calls: usize,
+    storage: u8,
+    error: Option<Error>
+}
+
+impl Registers<'_> {
+    /// Stops the program with an error. Handlers return a bare `bool`,
+    /// which is noticeably cheaper to pass around than a `Result`.
+    #[cold]
+    fn fail(&mut self, error: Error) -> bool {
+        self.error = Some(error);
+        false
+    }
+}
+
+/// Executes an operation, returning `false` if the program has to stop.
+type Handler = Box<dyn Fn(&mut Registers) -> bool>;
+
+/// Bytecode compiled into a tree of pre-bound closures.
+///
+/// Every operation becomes a closure with its operands captured. A loop becomes a single
+/// closure running the handlers of its body in a native `while`, so jump targets are
+/// resolved at compile time and neither an instruction pointer nor a loop stack is needed.
+/// Procedure bodies are compiled once and called natively. Programs forking with
+/// `SimOperation::Fork` are rejected, `run_with_io` runs them on `Machine` instead.
+pub struct ThreadedProgram {
+    root: Box<[Handler]>,
+    bodies: Box<[Box<[Handler]>]>
+}
+
+impl ThreadedProgram {
+    pub fn compile(ops: &[SimOperation]) -> Result<Self, Error> {
+        if ops.contains(&SimOperation::Fork) {
+            return Err(Error::Unsupported { feature: "`Y` threads", backend: "threaded" });
+        }
+        let mut bodies = Vec::new();
+        let root = Self::compile_block(ops, 0, &mut bodies);
+        Ok(Self { root, bodies: bodies.into_boxed_slice() })
+    }
+
+    /// Compiles the operations starting at `start` up to the end of the enclosing loop,
+    /// procedure or the program, adding the procedures defined there to `bodies`. The bytecode
+    /// keeps every loop and procedure body contiguous, ending with `JnzRestoreIP` or `Return`.
+    fn compile_block(ops: &[SimOperation], start: usize, bodies: &mut Vec<Box<[Handler]>>) -> Box<[Handler]> {
+        let mut handlers = Vec::new();
+        for (ip, op) in ops.iter().enumerate().skip(start) {
+            match op {
+                SimOperation::JnzRestoreIP { .. } | SimOperation::Return | SimOperation::EndProgram => break,
+                SimOperation::DefineProcedure { target_ip } => {
+                    let body = Self::compile_block(ops, *target_ip as usize, bodies);
+                    let index = bodies.len();
+                    bodies.push(body);
+                    handlers.push(Box::new(move |r: &mut Registers| {
+                        r.procedures[r.tape[r.tape_pos] as usize] = Some(index);
+                        true
+                    }) as Handler);
+                }
+                SimOperation::CallProcedure => handlers.push(Box::new(move |r: &mut Registers| {
+                    let procedure = r.tape[r.tape_pos];
+                    let Some(index) = r.procedures[procedure as usize] else {
+                        return r.fail(Error::UndefinedProcedure { procedure, instruction_pointer: ip });
+                    };
+                    if r.calls == MAX_CALL_DEPTH {
+                        return r.fail(Error::CallStackOverflow { instruction_pointer: ip });
+                    }
+                    r.calls += 1;
+                    let bodies = r.bodies;
+                    for handler in bodies[index].iter() {
+                        if !handler(r) {
+                            return false;
+                        }
+                    }
+                    r.calls -= 1;
+                    true
+                })),
+                SimOperation::JnzSaveIP { target_ip } => {
+                    let body = Self::compile_block(ops, *target_ip as usize, bodies);
+                    handlers.push(Box::new(move |r: &mut Registers| {
+                        while r.tape[r.tape_pos] != 0 {
+                            for handler in body.iter() {
+                                if !handler(r) {
+                                    return false;
+                                }
+                            }
+                        }
+                        true
+                    }) as Handler);
+                }
+                SimOperation::Noop => {}
+                _ => handlers.push(Self::compile_operation(ip, *op))
+            }
+        }
+        handlers.into_boxed_slice()
+    }
+
+    fn compile_operation(ip: usize, op: SimOperation) -> Handler {
+        let overflow = move || Error::TapeOverflow { instruction_pointer: ip };
+        let underflow = move || Error::TapeUnderflow { instruction_pointer: ip };
+        match op {
+            SimOperation::Inc(amount) => Box::new(move |r| {
+                r.tape[r.tape_pos] = r.tape[r.tape_pos].wrapping_add(amount);
+                true
+            }),
+            SimOperation::Dec(amount) => Box::new(move |r| {
+                r.tape[r.tape_pos] = r.tape[r.tape_pos].wrapping_sub(amount);
+                true
+            }),
+            SimOperation::IncTapePos(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    if r.tape_pos + offset >= r.tape.len() {
+                        return r.fail(overflow());
+                    }
+                    r.tape_pos += offset;
+                    true
+                })
+            }
+            SimOperation::DecTapePos(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    if r.tape_pos < offset {
+                        return r.fail(underflow());
+                    }
+                    r.tape_pos -= offset;
+                    true
+                })
+            }
+            SimOperation::IncTapePosUntilEmpty => Box::new(move |r| {
+                match r.tape[r.tape_pos..].iter().position(|&cell| cell == 0) {
+                    Some(distance) => {
+                        r.tape_pos += distance;
+                        true
+                    }
+                    None => {
+                        r.tape_pos = r.tape.len() - 1;
+                        r.fail(overflow())
+                    }
+                }
+            }),
+            SimOperation::DecTapePosUntilEmpty => Box::new(move |r| {
+                match r.tape[..=r.tape_pos].iter().rposition(|&cell| cell == 0) {
+                    Some(pos) => {
+                        r.tape_pos = pos;
+                        true
+                    }
+                    None => {
+                        r.tape_pos = 0;
+                        r.fail(underflow())
+                    }
+                }
+            }),
+            SimOperation::PutChar => Box::new(move |r| {
+                match r.output.write_all(&[r.tape[r.tape_pos]]) {
+                    Ok(()) => true,
+                    Err(err) => r.fail(err.into())
+                }
+            }),
+            SimOperation::GetChar => Box::new(move |r| {
+                let mut buf = [EOF_VALUE];
+                match r.input.read(&mut buf) {
+                    Ok(0) => r.tape[r.tape_pos] = EOF_VALUE,
+                    Ok(_) => r.tape[r.tape_pos] = buf[0],
+                    Err(err) => return r.fail(err.into())
+                }
+                true
+            }),
+            SimOperation::Clear => Box::new(move |r| {
+                r.tape[r.tape_pos] = 0;
+                true
+            }),
+            SimOperation::AddToTheRightAndClear(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    let value = r.tape[r.tape_pos];
+                    match r.tape.get_mut(r.tape_pos + offset) {
+                        Some(target) => *target = target.wrapping_add(value),
+                        None => return r.fail(overflow())
+                    }
+                    r.tape[r.tape_pos] = 0;
+                    true
+                })
+            }
+            SimOperation::DecFromTheRightAndClear(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    let value = r.tape[r.tape_pos];
+                    match r.tape.get_mut(r.tape_pos + offset) {
+                        Some(target) => *target = target.wrapping_sub(value),
+                        None => return r.fail(overflow())
+                    }
+                    r.tape[r.tape_pos] = 0;
+                    true
+                })
+            }
+            SimOperation::AddToTheLeftAndClear(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    let value = r.tape[r.tape_pos];
+                    if value != 0 {
+                        if r.tape_pos < offset {
+                            return r.fail(underflow());
+                        }
+                        let target = r.tape_pos - offset;
+                        r.tape[target] = r.tape[target].wrapping_add(value);
+                        r.tape[r.tape_pos] = 0;
+                    }
+                    true
+                })
+            }
+            SimOperation::DecFromTheLeftAndClear(offset) => {
+                let offset = offset as usize;
+                Box::new(move |r| {
+                    let value = r.tape[r.tape_pos];
+                    if value != 0 {
+                        if r.tape_pos < offset {
+                            return r.fail(underflow());
+                        }
+                        let target = r.tape_pos - offset;
+                        r.tape[target] = r.tape[target].wrapping_sub(value);
+                        r.tape[r.tape_pos] = 0;
+                    }
+                    true
+                })
+            }
+            SimOperation::Debug => Box::new(move |r| {
+                if let Err(err) = r.output.flush() {
+                    return r.fail(err.into());
+                }
+                eprintln!("{}", dump_tape(&r.tape, r.tape_pos));
+                true
+            }),
+            SimOperation::Fork => unreachable!(),
+            // Stopping without an error ends the program.
+            SimOperation::End => Box::new(|_| false),
+            SimOperation::Store => Box::new(|r| {
+                r.storage = r.tape[r.tape_pos];
+                true
+            }),
+            SimOperation::Retrieve => Box::new(|r| {
+                r.tape[r.tape_pos] = r.storage;
+                true
+            }),
+            SimOperation::ShiftLeft => Box::new(|r| {
+                r.tape[r.tape_pos] <<= 1;
+                true
+            }),
+            SimOperation::ShiftRight => Box::new(|r| {
+                r.tape[r.tape_pos] >>= 1;
+                true
+            }),
+            SimOperation::Not => Box::new(|r| {
+                r.tape[r.tape_pos] = !r.tape[r.tape_pos];
+                true
+            }),
+            SimOperation::Xor => Box::new(|r| {
+                r.tape[r.tape_pos] ^= r.storage;
+                true
+            }),
+            SimOperation::And => Box::new(|r| {
+                r.tape[r.tape_pos] &= r.storage;
+                true
+            }),
+            SimOperation::Or => Box::new(|r| {
+                r.tape[r.tape_pos] |= r.storage;
+                true
+            }),
+            SimOperation::Noop |
+            SimOperation::JnzSaveIP { .. } |
+            SimOperation::JnzRestoreIP { .. } |
+            SimOperation::DefineProcedure { .. } |
+            SimOperation::CallProcedure |
+            SimOperation::Return |
+            SimOperation::EndProgram => unreachable!("handled by compile_block")
+        }
+    }
+
+    /// Runs the program on a fresh tape, flushing the output afterwards.
+    pub fn run<I: Read, O: Write>(&self, config: Config, mut input: I, mut output: O) -> Result<(), Error> {
+        let mut registers = Registers {
+            tape: vec![0; config.tape_size],
+            tape_pos: 0,
+            input: &mut input,
+            output: &mut output,
+            bodies: &self.bodies,
+            procedures: [None; 256],
+            calls: 0,
+            storage: 0,
+            error: None
+        };
+        for handler in self.root.iter() {
+            if !handler(&mut registers) {
+                break;
+            }
+        }
+        if let Some(err) = registers.error {
+            return Err(err);
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use brain_fuck_parser::parse_bf;
+    use crate::{Config, Error, Machine};
+    use super::ThreadedProgram;
+
+    fn run_threaded(code: &str, config: Config, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut output = Vec::new();
+        ThreadedProgram::compile(&parse_bf(code).compile_bytecode()).unwrap().run(config, input, &mut output)?;
+        Ok(output)
+    }
+
+    fn run_switch(code: &str, config: Config, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut machine = Machine::parse(code, config, input, Vec::new())?;
+        machine.run()?;
+        Ok(machine.into_output())
+    }
+
+    #[test]
+    fn output_matches_switch_backend() {
+        let programs = [
+            "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+            ",+[-.,+]",
+            ">>++++[<++++[<++++>-]>-]<<.[-]++[>+++<-]>[<+>-]<.>+++++[->+>++<<]>>[-<<->>]<<.[.-]",
+            "++++[>++++[>++++<-]<-]>>[<<+>>-]<<[>+>+<<-]>>.<.<[-]>>>++[<<+>[<+>-]>-]<<<.>."
+        ];
+        for code in programs {
+            let config = Config::default();
+            assert_eq!(
+                run_switch(code, config, b"threaded").unwrap(),
+                run_threaded(code, config, b"threaded").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn tape_bounds_are_checked() {
+        let config = Config { tape_size: 4, ..Config::default() };
+        assert!(matches!(
+            run_threaded("+[>+]", config, b""),
+            Err(Error::TapeOverflow { instruction_pointer: 3 })
+        ));
+        assert!(matches!(
+            run_threaded("><<", config, b""),
+            Err(Error::TapeUnderflow { instruction_pointer: 1 })
+        ));
+        assert!(matches!(
+            run_threaded("+>+>+>+[<]", config, b""),
+            Err(Error::TapeUnderflow { .. })
+        ));
+        assert!(matches!(
+            run_threaded("+>+>+>+[>]", config, b""),
+            Err(Error::TapeOverflow { .. })
+        ));
+    }
+}
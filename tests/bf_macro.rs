@@ -0,0 +1,181 @@
+use std::io::{Cursor, Write};
+use brain_fuck_interpreter::Config;
+use proc_macro_bf::bf;
+
+const HELLO: &str = "Hello World!\n";
+
+bf!(hello_vec, "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+    output = vec, quiet);
+bf!(pub(crate) echo_slice, ",.,.,.,.,.", input = slice, output = vec, quiet);
+bf!(pub(self) echo_io, ",.,.,.,.,.,.", input = reader, output = writer, quiet);
+bf!(tape_of_bytes, "+>++>+++<<", output = vec, return_tape, tape_size = 4, quiet);
+bf!(tape_of_words, "++++++++++++++++[>++++++++++++++++<-]", return_tape, tape_size = 2, cell = u16, quiet);
+bf!(long_runs_of_words, "++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++>-----------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------", return_tape, tape_size = 2, cell = u16, quiet);
+bf!(read_past_end, ",>,", input = slice, return_tape, tape_size = 2, cell = u32, quiet,);
+bf!(precomputed_tape, "+>++>+++<<.", output = writer, return_tape, tape_size = 4, precompute, quiet);
+bf!(dumps_tape, "+>++#<#.", output = vec, extensions = "debug", quiet);
+bf!(dumps_unchecked_tape, "+>++#<#.", output = writer, unsafe, padding = 2, extensions = "debug", quiet);
+bf!(padded_tape, "<+>>>+", return_tape, tape_size = 2, padding = 2, unsafe, quiet);
+bf!(procedures_vec, "(++++++++[>++++++++<-]>+.[-]<)+(>[.-<:>]<)>+++<:-:", output = vec, precompute, extensions = "pbrain", quiet);
+bf!(procedures_slice, ",(.+):", input = slice, output = vec, return_tape, tape_size = 2, unsafe, extensions = "pbrain", quiet);
+bf!(procedures_io, ",(.+):(.):", input = reader, output = writer, extensions = "pbrain", quiet);
+bf!(undefined_procedure, ",(.)+:", input = slice, extensions = "pbrain", quiet);
+bf!(extended_vec, "++++{$>+++^~}.<-!{|.,}.&.+(.@):+.", input = slice, output = vec, extensions = "pbrain,ebf", quiet);
+bf!(extended_io, ",$>+^.@.", input = reader, output = writer, extensions = "ebf", quiet);
+
+/// Fails every write, to check errors are propagated.
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn output_is_returned() {
+    assert_eq!(HELLO.as_bytes(), hello_vec());
+}
+
+#[test]
+fn input_is_taken_from_a_slice() {
+    assert_eq!(b"slice".to_vec(), echo_slice(b"slice"));
+}
+
+#[test]
+fn input_and_output_go_through_io_traits() {
+    let mut output = Vec::new();
+    echo_io(Cursor::new(b"reader"), &mut output).unwrap();
+    assert_eq!(b"reader".to_vec(), output);
+    let err = echo_io(Cursor::new(b"x"), BrokenPipe).unwrap_err();
+    assert_eq!(std::io::ErrorKind::BrokenPipe, err.kind());
+}
+
+#[test]
+fn debug_dumps_leave_the_output_alone() {
+    assert_eq!(vec![1], dumps_tape());
+    let mut output = Vec::new();
+    dumps_unchecked_tape(&mut output).unwrap();
+    assert_eq!(vec![1], output);
+}
+
+#[test]
+fn procedures_are_called() {
+    assert_eq!(vec![3, 2, 1, b'A'], procedures_vec());
+    assert_eq!((b"a".to_vec(), vec![b'b', 0]), procedures_slice(b"a"));
+    let mut output = Vec::new();
+    procedures_io(Cursor::new(b"a"), &mut output).unwrap();
+    assert_eq!(b"ab".to_vec(), output);
+    let err = procedures_io(Cursor::new(b"a"), BrokenPipe).unwrap_err();
+    assert_eq!(std::io::ErrorKind::BrokenPipe, err.kind());
+}
+
+#[test]
+fn extended_operations_run() {
+    assert_eq!(vec![122, 24, 32, 0, 1], extended_vec(b"A"));
+    let mut output = Vec::new();
+    extended_io(Cursor::new(b"!"), &mut output).unwrap();
+    assert_eq!(vec![32], output);
+}
+
+#[test]
+#[should_panic(expected = "called undefined procedure 99")]
+fn undefined_procedures_panic() {
+    undefined_procedure(b"b");
+}
+
+#[test]
+fn tape_is_returned() {
+    assert_eq!((Vec::new(), vec![1u8, 2, 3, 0]), tape_of_bytes());
+    assert_eq!(vec![0u16, 256], tape_of_words());
+    assert_eq!(vec![300u16, 65279], long_runs_of_words());
+    assert_eq!(vec![b'a' as u32, 255], read_past_end(b"a"));
+    let mut output = Vec::new();
+    assert_eq!(vec![1u8, 2, 3, 0], precomputed_tape(&mut output).unwrap());
+    assert_eq!(vec![1u8], output);
+    // The padding is not part of the tape.
+    assert_eq!(vec![0u8, 0], padded_tape());
+}
+
+/// Defines a checked, an unchecked, a precomputed and a partially evaluated `bf!` function with slice input and vec output for
+/// every program, along with `COMPARED`, the programs and their functions.
+macro_rules! compared {
+    ($($name:ident => $code:tt),* $(,)?) => {
+        mod checked {
+            use super::bf;
+            $(bf!($name, $code, input = slice, output = vec, tape_size = 64, quiet);)*
+        }
+        mod unchecked {
+            use super::bf;
+            $(bf!($name, $code, input = slice, output = vec, tape_size = 64, unsafe, padding = 4, quiet);)*
+        }
+        mod precomputed {
+            use super::bf;
+            $(bf!($name, $code, input = slice, output = vec, tape_size = 64, precompute, quiet);)*
+        }
+        mod prefix_evaluated {
+            use super::bf;
+            $(bf!($name, $code, input = slice, output = vec, tape_size = 64, evaluate_prefix, quiet);)*
+        }
+        const COMPARED: &[(&str, [fn(&[u8]) -> Vec<u8>; 4])] = &[$((
+            $code,
+            [checked::$name, unchecked::$name, precomputed::$name, prefix_evaluated::$name]
+        )),*];
+    };
+}
+
+compared! {
+    wrap_below_zero => "-.+.",
+    wrap_above_max => ">+++++++++++++++[-<+++++++++++++++++++>]<.",
+    add_to_the_right => "-->-<[->+<]>.",
+    dec_from_the_right => ">+<[->-<]>.",
+    add_to_the_left => ">->--<[-<+>]<.",
+    dec_from_the_left => ">>>-[-<<<->>>]<<<.",
+    input_past_the_end => ",.,.,.+.",
+    echo_until_eof => ",+[-.,+]",
+    output_before_input => "++++++++[>++++++++<-]>+.+.<+++[->>+<<],.>>.",
+    long_series => r#"++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+        ++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+        ++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++."#,
+}
+
+#[test]
+fn output_matches_interpreter() {
+    for (code, functions) in COMPARED {
+        let mut expected = Vec::new();
+        brain_fuck_interpreter::run_with_io(code, Config::default(), &b"ab"[..], &mut expected).unwrap();
+        for function in functions {
+            assert_eq!(expected, function(b"ab"), "{code}");
+        }
+    }
+}
+
+/// Builds the `bf!` functions of `tests/fixtures/wrapping` in debug, with overflow checks, and in
+/// release, and compares what they write with the interpreter's output.
+#[test]
+fn wrapping_matches_interpreter_in_debug_and_release() {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/wrapping/Cargo.toml");
+    for profile in [&[][..], &["--release"][..]] {
+        let output = std::process::Command::new(&cargo)
+            .args(["run", "--quiet", "--manifest-path", manifest, "--target-dir", env!("CARGO_TARGET_TMPDIR")])
+            .args(profile)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{profile:?}: {}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!stdout.is_empty(), "{profile:?}");
+        let mut lines = stdout.lines();
+        while let (Some(code), Some(bytes)) = (lines.next(), lines.next()) {
+            let mut expected = Vec::new();
+            brain_fuck_interpreter::run_with_io(code, Config::default(), &b""[..], &mut expected).unwrap();
+            let expected = expected.repeat(2);
+            let actual: Vec<u8> = bytes.split_whitespace().map(|byte| byte.parse().unwrap()).collect();
+            assert_eq!(expected, actual, "{code} {profile:?}");
+        }
+    }
+}
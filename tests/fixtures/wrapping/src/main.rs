@@ -0,0 +1,32 @@
+//! `bf!` functions whose cells wrap around, built in debug and release by `tests/bf_macro.rs`.
+//! Prints every program on a line, followed by a line with the bytes of its output written by
+//! the checked and then the unchecked function.
+
+use std::io::Write;
+use proc_macro_bf::bf;
+
+macro_rules! programs {
+    ($($checked:ident, $unchecked:ident => $code:tt),* $(,)?) => {
+        $(
+            bf!($checked, $code, output = vec, quiet);
+            bf!($unchecked, $code, output = vec, unsafe, padding = 4, quiet);
+        )*
+        const PROGRAMS: &[(&str, fn() -> Vec<u8>, fn() -> Vec<u8>)] = &[$(($code, $checked, $unchecked)),*];
+    };
+}
+
+programs! {
+    below_zero, below_zero_unchecked => "-.+.>+++++++++++++++[-<+++++++++++++++++++>]<.",
+    transfers, transfers_unchecked => "-->-<[->+<]>.>+<[->-<]>.",
+    transfers_to_the_left, transfers_to_the_left_unchecked => ">->--<[-<+>]<.>>>[-<<<->>>]<<<.",
+    hello, hello_unchecked => "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.",
+    long_runs, long_runs_unchecked => "-------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------------.[-]+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++.",
+}
+
+fn main() {
+    let mut stdout = std::io::stdout().lock();
+    for (code, checked, unchecked) in PROGRAMS {
+        let output: Vec<String> = checked().into_iter().chain(unchecked()).map(|byte| byte.to_string()).collect();
+        writeln!(stdout, "{code}\n{}", output.join(" ")).unwrap();
+    }
+}